@@ -4,9 +4,12 @@ use std::io::Result;
 use std::io::Write;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
+use std::net::SocketAddrV6;
 
 use byteorder::BigEndian;
+use byteorder::LittleEndian;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
 
@@ -17,7 +20,6 @@ mod u24;
 pub type CursorWriter<'a> = Cursor<&'a mut Vec<u8>>;
 pub type CursorReader<'a> = Cursor<&'a [u8]>;
 pub use big_endian::*;
-pub use little_endian::*;
 pub use u24::*;
 
 pub trait Den {
@@ -85,10 +87,7 @@ impl Den for String {
         bytes.read_exact(&mut raw_str)?;
         match String::from_utf8(raw_str) {
             Ok(p) => Ok(p),
-            Err(e) => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                e.to_string(),
-            )),
+            Err(e) => Err(std::io::Error::other(e.to_string())),
         }
     }
 
@@ -102,46 +101,73 @@ impl Den for String {
     }
 }
 
+// AF_INET6 as written by RakNet (Windows sockaddr_in6 layout).
+const AF_INET6: u16 = 23;
+
 impl Den for SocketAddr {
     fn decode(bytes: &mut CursorReader) -> Result<Self> {
         let ip_ver = bytes.read_u8()?;
-        if ip_ver == 4 {
-            let ip = Ipv4Addr::new(
-                0xff - bytes.read_u8()?,
-                0xff - bytes.read_u8()?,
-                0xff - bytes.read_u8()?,
-                0xff - bytes.read_u8()?,
-            );
-            let port = bytes.read_u16::<BigEndian>()?;
-            Ok(SocketAddr::new(IpAddr::V4(ip), port))
-        } else {
-            todo!()
+        match ip_ver {
+            4 => {
+                let ip = Ipv4Addr::new(
+                    0xff - bytes.read_u8()?,
+                    0xff - bytes.read_u8()?,
+                    0xff - bytes.read_u8()?,
+                    0xff - bytes.read_u8()?,
+                );
+                let port = bytes.read_u16::<BigEndian>()?;
+                Ok(SocketAddr::new(IpAddr::V4(ip), port))
+            }
+            6 => {
+                let _family = bytes.read_u16::<LittleEndian>()?;
+                let port = bytes.read_u16::<BigEndian>()?;
+                let flowinfo = bytes.read_u32::<BigEndian>()?;
+                let mut octets = [0u8; 16];
+                bytes.read_exact(&mut octets)?;
+                let scope_id = bytes.read_u32::<BigEndian>()?;
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(octets),
+                    port,
+                    flowinfo,
+                    scope_id,
+                )))
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid ip version {}", ip_ver),
+            )),
         }
     }
 
     fn encode(&self, bytes: &mut CursorWriter) -> Result<()> {
-        if self.is_ipv4() {
-            bytes.write_u8(0x4)?;
-            let ip_bytes = match self.ip() {
-                IpAddr::V4(ip) => ip.octets().to_vec(),
-                _ => vec![0; 4],
-            };
-
-            bytes.write_u8(0xff - ip_bytes[0])?;
-            bytes.write_u8(0xff - ip_bytes[1])?;
-            bytes.write_u8(0xff - ip_bytes[2])?;
-            bytes.write_u8(0xff - ip_bytes[3])?;
-            bytes.write_u16::<BigEndian>(self.port())?;
-            Ok(())
-        } else {
-            todo!()
+        match self {
+            SocketAddr::V4(addr) => {
+                bytes.write_u8(0x4)?;
+                let ip_bytes = addr.ip().octets();
+
+                bytes.write_u8(0xff - ip_bytes[0])?;
+                bytes.write_u8(0xff - ip_bytes[1])?;
+                bytes.write_u8(0xff - ip_bytes[2])?;
+                bytes.write_u8(0xff - ip_bytes[3])?;
+                bytes.write_u16::<BigEndian>(addr.port())?;
+                Ok(())
+            }
+            SocketAddr::V6(addr) => {
+                bytes.write_u8(0x6)?;
+                bytes.write_u16::<LittleEndian>(AF_INET6)?;
+                bytes.write_u16::<BigEndian>(addr.port())?;
+                bytes.write_u32::<BigEndian>(addr.flowinfo())?;
+                bytes.write_all(&addr.ip().octets())?;
+                bytes.write_u32::<BigEndian>(addr.scope_id())?;
+                Ok(())
+            }
         }
     }
 
     fn size(&self) -> usize {
         match self {
             SocketAddr::V4(_) => 7,
-            SocketAddr::V6(_) => todo!(),
+            SocketAddr::V6(_) => 29,
         }
     }
 }
@@ -155,7 +181,14 @@ pub struct MAGIC;
 impl DenWith<()> for MAGIC {
     fn decode(bytes: &mut CursorReader) -> Result<()> {
         let mut dst = [0u8; 16];
-        bytes.read_exact(&mut dst)
+        bytes.read_exact(&mut dst)?;
+        if dst != OFFLINE_DATA {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid offline magic",
+            ));
+        }
+        Ok(())
     }
 
     fn encode(_: &(), bytes: &mut CursorWriter) -> Result<()> {
@@ -22,12 +22,12 @@ pub fn derive_den(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
             Some(AttributeType::DenWith(with)) => {
                 let den_with = Ident::new(&with.value(), with.span());
-                return quote! {
+                quote! {
                     #ident : <#den_with as DenWith<#ty>>::decode(bytes)?
-                };
+                }
             }
             None => {
-                return quote! {
+                quote! {
                     #ident : Den::decode(bytes)?
                 }
             }
@@ -47,12 +47,12 @@ pub fn derive_den(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
             Some(AttributeType::DenWith(with)) => {
                 let den_with = Ident::new(&with.value(), with.span());
-                return quote! {
+                quote! {
                     <#den_with as DenWith<#ty>>::encode(&self.#ident, bytes)?;
-                };
+                }
             }
             None => {
-                return quote! {
+                quote! {
                     Den::encode(&self.#ident,bytes)?;
                 }
             }
@@ -71,12 +71,12 @@ pub fn derive_den(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
             Some(AttributeType::DenWith(with)) => {
                 let den_with = Ident::new(&with.value(), with.span());
-                return quote! {
+                quote! {
                     + <#den_with as DenWith<#ty>>::size(&self.#ident)
-                };
+                }
             }
             None => {
-                return quote! {
+                quote! {
                     + Den::size(&self.#ident)
                 }
             }
@@ -127,7 +127,7 @@ fn parse_attr_each(attrs: &[Attribute]) -> std::option::Option<AttributeType> {
                 paren_token: _,
                 ref nested,
             }) => {
-                (path.get_ident()? == "den").then(|| ())?;
+                (path.get_ident()? == "den").then_some(())?;
 
                 if let NestedMeta::Meta(Meta::NameValue(MetaNameValue {
                     path,
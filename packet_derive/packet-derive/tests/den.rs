@@ -54,7 +54,31 @@ fn den() {
     let encoded = bytes.into_inner();
     assert_eq!(hoge.size(), encoded.len());
 
-    let mut cursor = std::io::Cursor::new(&encoded as &[u8]);
+    let mut cursor = std::io::Cursor::new(encoded as &[u8]);
     let hoge2 = Hoge::decode(&mut cursor).unwrap();
     assert_eq!(hoge, hoge2)
 }
+
+#[test]
+fn socket_addr() {
+    let addrs: [std::net::SocketAddr; 3] = [
+        "127.0.0.1:19132".parse().unwrap(),
+        "[::1]:19133".parse().unwrap(),
+        std::net::SocketAddr::V6(std::net::SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            19132,
+            7,
+            3,
+        )),
+    ];
+    for addr in addrs {
+        let mut dst = vec![];
+        let mut bytes: CursorWriter = std::io::Cursor::new(&mut dst);
+        addr.encode(&mut bytes).unwrap();
+        let encoded = bytes.into_inner();
+        assert_eq!(addr.size(), encoded.len());
+
+        let mut cursor = std::io::Cursor::new(encoded as &[u8]);
+        assert_eq!(addr, std::net::SocketAddr::decode(&mut cursor).unwrap());
+    }
+}
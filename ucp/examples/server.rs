@@ -1,25 +1,33 @@
 use std::net::SocketAddr;
 
-use ucp::{Reliability, UcpListener, UcpSession};
+use tokio_stream::StreamExt;
+use ucp::{BedrockMotd, Reliability, UcpListener, UcpSession};
 
 #[tokio::main]
 async fn main() {
     let addr: SocketAddr = "127.0.0.1:19132".parse().unwrap();
-    let mut ucp = UcpListener::bind(addr, 0x114514, "MCPE;Dedicated Server;390;1.14.60;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;".to_owned())
+    let motd = BedrockMotd {
+        edition: "MCPE".to_owned(),
+        motd: "Dedicated Server".to_owned(),
+        protocol: 390,
+        version: "1.14.60".to_owned(),
+        online_players: 0,
+        max_players: 10,
+        server_id: Some(13253860892328930865),
+        level_name: Some("Bedrock level".to_owned()),
+        game_mode: Some("Survival".to_owned()),
+        game_mode_id: Some(1),
+        port_v4: Some(19132),
+        port_v6: Some(19133),
+    };
+    let mut ucp = UcpListener::bind(addr, 0x114514, motd.into())
         .await
         .unwrap();
-    loop {
-        let into_session = ucp.accept().await.unwrap();
-        tokio::spawn(async move {
-            tokio::select! {
-                re = into_session => {
-                    if let Ok(session) = re {
-                        handle(session).await
-                    }
-                },
-                _ = tokio::time::sleep(std::time::Duration::from_secs(5)) => {}
-            };
-        });
+    let mut incoming = ucp.incoming();
+    while let Some(session) = incoming.next().await {
+        if let Ok(session) = session {
+            tokio::spawn(handle(session));
+        }
     }
 }
 
@@ -0,0 +1,184 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::canonical_addr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Accept,
+    /// Refuse with ConnectionRequestFailed.
+    Reject,
+    /// Refuse with ConnectionBanned.
+    Ban,
+}
+
+pub trait AdmissionPolicy: Send + Sync {
+    /// Consulted on OpenConnectionRequest1 (`guid` is `None`) and OpenConnectionRequest2.
+    fn on_open_connection(&self, addr: SocketAddr, guid: Option<u64>, mtu: u16) -> Decision;
+
+    /// Consulted on ConnectionRequest, once the session exists.
+    fn on_connection_request(&self, _addr: SocketAddr, _guid: u64) -> Decision {
+        Decision::Accept
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> Self {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.min(max);
+        // Clients are matched by their canonical address, so IPv4-mapped networks
+        // are kept as IPv4.
+        match addr {
+            IpAddr::V6(v6) if prefix >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => Self {
+                    addr: IpAddr::V4(v4),
+                    prefix: prefix - 96,
+                },
+                None => Self { addr, prefix },
+            },
+            _ => Self { addr, prefix },
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical_addr(SocketAddr::new(ip, 0)).ip();
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), ip) => {
+                // Wider IPv6 networks, like ::/0, may span the IPv4-mapped range.
+                let ip = match ip {
+                    IpAddr::V4(v4) => v4.to_ipv6_mapped(),
+                    IpAddr::V6(v6) => v6,
+                };
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpCidr {
+    fn from(addr: IpAddr) -> Self {
+        Self::new(addr, 128)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseCidrError;
+
+impl std::fmt::Display for ParseCidrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid CIDR notation")
+    }
+}
+
+impl std::error::Error for ParseCidrError {}
+
+impl FromStr for IpCidr {
+    type Err = ParseCidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').unwrap_or((s, "128"));
+        let addr = addr.parse().map_err(|_| ParseCidrError)?;
+        let prefix = prefix.parse().map_err(|_| ParseCidrError)?;
+        Ok(Self::new(addr, prefix))
+    }
+}
+
+#[derive(Default)]
+pub struct BanList {
+    entries: Mutex<Vec<(IpCidr, Option<Instant>)>>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bans `cidr` for `duration`, or forever if `None`.
+    pub fn ban(&self, cidr: impl Into<IpCidr>, duration: Option<Duration>) {
+        let cidr = cidr.into();
+        let expiry = duration.map(|d| Instant::now() + d);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(c, _)| *c != cidr);
+        entries.push((cidr, expiry));
+    }
+
+    pub fn unban(&self, cidr: impl Into<IpCidr>) -> bool {
+        let cidr = cidr.into();
+        let mut entries = self.entries.lock().unwrap();
+        let len = entries.len();
+        entries.retain(|(c, _)| *c != cidr);
+        entries.len() != len
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(_, expiry)| expiry.map(|e| e > now).unwrap_or(true));
+        entries.iter().any(|(cidr, _)| cidr.contains(ip))
+    }
+}
+
+impl AdmissionPolicy for BanList {
+    fn on_open_connection(&self, addr: SocketAddr, _guid: Option<u64>, _mtu: u16) -> Decision {
+        if self.is_banned(addr.ip()) {
+            Decision::Ban
+        } else {
+            Decision::Accept
+        }
+    }
+
+    fn on_connection_request(&self, addr: SocketAddr, guid: u64) -> Decision {
+        self.on_open_connection(addr, Some(guid), 0)
+    }
+}
+
+// The built-in ban list followed by the user supplied policy.
+#[derive(Default)]
+pub(crate) struct Admission {
+    pub bans: Arc<BanList>,
+    pub policy: RwLock<Option<Arc<dyn AdmissionPolicy>>>,
+}
+
+impl Admission {
+    fn policy(&self) -> Option<Arc<dyn AdmissionPolicy>> {
+        self.policy.read().unwrap().clone()
+    }
+
+    pub fn open_connection(&self, addr: SocketAddr, guid: Option<u64>, mtu: u16) -> Decision {
+        let addr = canonical_addr(addr);
+        match self.bans.on_open_connection(addr, guid, mtu) {
+            Decision::Accept => self
+                .policy()
+                .map(|p| p.on_open_connection(addr, guid, mtu))
+                .unwrap_or(Decision::Accept),
+            decision => decision,
+        }
+    }
+
+    pub fn connection_request(&self, addr: SocketAddr, guid: u64) -> Decision {
+        let addr = canonical_addr(addr);
+        match self.bans.on_connection_request(addr, guid) {
+            Decision::Accept => self
+                .policy()
+                .map(|p| p.on_connection_request(addr, guid))
+                .unwrap_or(Decision::Accept),
+            decision => decision,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use std::{collections::VecDeque, time::Duration};
+
+// Number of recent samples the offset is picked from.
+const WINDOW: usize = 8;
+
+// NTP-style estimate of how far the peer's clock is ahead of ours, in milliseconds.
+// Queueing delay skews a sample by up to half its round-trip time, so the estimate
+// is taken from the fastest recent exchange.
+#[derive(Default)]
+pub(crate) struct ClockOffset {
+    samples: VecDeque<(Duration, i64)>,
+}
+
+impl ClockOffset {
+    // `sent` is our timestamp in the ping, `remote` the peer's timestamp in the pong.
+    pub fn sample(&mut self, sent: u64, remote: u64, rtt: Duration) {
+        let midpoint = sent as i64 + (rtt / 2).as_millis() as i64;
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((rtt, remote as i64 - midpoint));
+    }
+
+    pub fn offset(&self) -> Option<i64> {
+        self.samples
+            .iter()
+            .min_by_key(|(rtt, _)| *rtt)
+            .map(|(_, offset)| *offset)
+    }
+}
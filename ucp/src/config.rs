@@ -0,0 +1,235 @@
+use std::time::Duration;
+
+use crate::{pmtu::BASE_MTU, ServerKey, MAX_MTU_SIZE};
+
+// Frame lengths travel as a u16 count of bits, so no datagram may carry more than this.
+const MTU_LIMIT: u16 = 8192;
+// Shortest tick and retry intervals; zero would make the session spin.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// What a listener does when a client connects with the address or GUID of a live session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Refuse the new connection with AlreadyConnected, as RakNet does.
+    #[default]
+    RejectNew,
+    /// Kick the existing session and accept the new connection.
+    KickExisting,
+}
+
+#[derive(Clone, Debug)]
+pub struct UcpConfig {
+    pub(crate) max_mtu_size: u16,
+    pub(crate) mtu_ladder: Vec<u16>,
+    pub(crate) mtu_discovery: bool,
+    pub(crate) handshake_attempts: usize,
+    pub(crate) handshake_retry_interval: Duration,
+    pub(crate) accept_timeout: Duration,
+    pub(crate) tick_interval: Duration,
+    pub(crate) ping_interval: Duration,
+    pub(crate) idle_timeout: Duration,
+    pub(crate) min_rto: Duration,
+    pub(crate) max_rto: Duration,
+    pub(crate) max_resends: u32,
+    pub(crate) close_timeout: Duration,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) duplicate_policy: DuplicatePolicy,
+    pub(crate) connection_migration: bool,
+    pub(crate) handshake_cookies: bool,
+    pub(crate) server_key: Option<ServerKey>,
+    pub(crate) require_encryption: bool,
+    pub(crate) use_encryption: bool,
+    pub(crate) pinned_server_key: Option<[u8; 32]>,
+    pub(crate) session_channel_capacity: usize,
+    pub(crate) listener_channel_capacity: usize,
+}
+
+impl Default for UcpConfig {
+    fn default() -> Self {
+        Self {
+            max_mtu_size: MAX_MTU_SIZE,
+            mtu_ladder: vec![1496, 1204, 584],
+            mtu_discovery: true,
+            handshake_attempts: 4,
+            handshake_retry_interval: Duration::from_millis(500),
+            accept_timeout: Duration::from_secs(5),
+            tick_interval: Duration::from_millis(50),
+            ping_interval: Duration::from_millis(4500),
+            idle_timeout: Duration::from_secs(10),
+            min_rto: Duration::from_millis(1000),
+            max_rto: Duration::from_secs(10),
+            max_resends: 4,
+            close_timeout: Duration::from_secs(5),
+            max_connections: None,
+            duplicate_policy: DuplicatePolicy::RejectNew,
+            connection_migration: false,
+            handshake_cookies: false,
+            server_key: None,
+            require_encryption: false,
+            use_encryption: false,
+            pinned_server_key: None,
+            session_channel_capacity: 128,
+            listener_channel_capacity: 32,
+        }
+    }
+}
+
+impl UcpConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Upper bound of the negotiated MTU, clamped to 576..=8192. Receive buffers are
+    /// sized from it.
+    pub fn max_mtu_size(mut self, mtu: u16) -> Self {
+        self.max_mtu_size = mtu.clamp(BASE_MTU, MTU_LIMIT);
+        self
+    }
+
+    /// MTU sizes tried in order by OpenConnectionRequest1, each clamped to 576..=8192.
+    pub fn mtu_ladder(mut self, ladder: Vec<u16>) -> Self {
+        self.mtu_ladder = ladder
+            .into_iter()
+            .map(|mtu| mtu.clamp(BASE_MTU, MTU_LIMIT))
+            .collect();
+        self
+    }
+
+    /// Fall back to a small MTU when large datagrams keep getting lost while small ones
+    /// arrive, and probe back up towards the MTU negotiated by the handshake.
+    pub fn mtu_discovery(mut self, enabled: bool) -> Self {
+        self.mtu_discovery = enabled;
+        self
+    }
+
+    /// Number of requests sent per handshake step (and per MTU size) before giving up.
+    /// At least 1.
+    pub fn handshake_attempts(mut self, attempts: usize) -> Self {
+        self.handshake_attempts = attempts.max(1);
+        self
+    }
+
+    /// At least 1ms.
+    pub fn handshake_retry_interval(mut self, interval: Duration) -> Self {
+        self.handshake_retry_interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// Time an incoming connection has to finish ConnectionRequest/NewIncomingConnections.
+    pub fn accept_timeout(mut self, timeout: Duration) -> Self {
+        self.accept_timeout = timeout;
+        self
+    }
+
+    /// Interval of the session ticker which flushes acks and resends. At least 1ms.
+    pub fn tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// Interval of the ConnectedPing keepalive, which also feeds the RTT and clock
+    /// offset estimates. At least 1ms.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// A session ends once nothing was received from the peer for this long. Halfway
+    /// there, a DetectLostConnections probe asks the peer for an ack.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn min_rto(mut self, rto: Duration) -> Self {
+        self.min_rto = rto;
+        self
+    }
+
+    pub fn max_rto(mut self, rto: Duration) -> Self {
+        self.max_rto = rto;
+        self
+    }
+
+    /// Resends of a datagram before the connection is considered lost.
+    pub fn max_resends(mut self, resends: u32) -> Self {
+        self.max_resends = resends;
+        self
+    }
+
+    /// How long `UcpSession::close` waits for outstanding datagrams to be acked.
+    pub fn close_timeout(mut self, timeout: Duration) -> Self {
+        self.close_timeout = timeout;
+        self
+    }
+
+    /// Maximum number of sessions a listener holds at once. Unlimited by default.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = policy;
+        self
+    }
+
+    /// Let an encrypted session follow its client to a new port, as after a NAT
+    /// rebinding. An authenticated datagram from there triggers a challenge, and the
+    /// session moves once the client answers it. Plain sessions carry no proof of
+    /// identity and never move.
+    pub fn connection_migration(mut self, enabled: bool) -> Self {
+        self.connection_migration = enabled;
+        self
+    }
+
+    /// Require clients to echo a cookie from OpenConnectionReply1 before the listener
+    /// allocates a session. Off by default, as not every client supports it.
+    pub fn handshake_cookies(mut self, enabled: bool) -> Self {
+        self.handshake_cookies = enabled;
+        self
+    }
+
+    /// Makes a listener offer encrypted sessions, keyed by `key`. Plain clients are
+    /// still accepted unless `require_encryption` is set.
+    pub fn server_key(mut self, key: ServerKey) -> Self {
+        self.server_key = Some(key);
+        self
+    }
+
+    /// Makes a listener with a `server_key` refuse clients that do not encrypt.
+    pub fn require_encryption(mut self, required: bool) -> Self {
+        self.require_encryption = required;
+        self
+    }
+
+    /// Makes `UcpSession::connect` insist on an encrypted session.
+    ///
+    /// Without `pin_server_key`, whatever key the server presents is trusted. That
+    /// keeps passive eavesdroppers out, but an attacker on the path can answer the
+    /// handshake with its own key and read everything.
+    pub fn use_encryption(mut self, enabled: bool) -> Self {
+        self.use_encryption = enabled;
+        self
+    }
+
+    /// Only connect to a server holding the key behind `public_key`. Implies `use_encryption`.
+    pub fn pin_server_key(mut self, public_key: [u8; 32]) -> Self {
+        self.pinned_server_key = Some(public_key);
+        self.use_encryption = true;
+        self
+    }
+
+    /// Packets buffered per session for `recv`. While they are full, the session stops
+    /// taking datagrams, and the peer sends them again later. At least 1.
+    pub fn session_channel_capacity(mut self, capacity: usize) -> Self {
+        self.session_channel_capacity = capacity.max(1);
+        self
+    }
+
+    /// Sessions buffered for `accept`. At least 1.
+    pub fn listener_channel_capacity(mut self, capacity: usize) -> Self {
+        self.listener_channel_capacity = capacity.max(1);
+        self
+    }
+}
@@ -1,45 +1,201 @@
 use packet_derive::*;
-use std::io::Cursor;
+use std::collections::VecDeque;
+use std::io::{Cursor, ErrorKind};
 use std::net::SocketAddr;
 use std::time::Duration;
 use std::time::Instant;
-use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 
+use crate::clock::ClockOffset;
+use crate::config::UcpConfig;
+use crate::crypto::{Opener, SessionKeys};
 use crate::packets::*;
 use crate::receive::ReceiveQueue;
+use crate::rtt::{RttMeter, RttStats};
 use crate::send::DatagramSender;
+use crate::stats::ConnectionStats;
 use crate::system_packets::*;
 use crate::time;
-use crate::ConnEvent;
 use crate::Udp;
+use crate::{DisconnectReason, PacketSender, PeerAddr, Result, UcpError};
 
 const DATAGRAM_FLAG: u8 = 0x80;
 const ACK_FLAG: u8 = 0x40;
 const NACK_FLAG: u8 = 0x20;
+// Pings nobody waits for are forgotten past this many, their pongs are likely lost.
+const MAX_PENDING_PINGS: usize = 8;
+// A ping unanswered for this many retransmission timeouts is given up on.
+const PING_TIMEOUT_RTOS: u32 = 4;
+// Set in the timestamp of path challenges. Ping timestamps are Unix milliseconds, far
+// below 2^63, so the two never mix.
+const PATH_CHALLENGE_FLAG: u64 = 1 << 63;
+// An unanswered path challenge is repeated after this long, if the peer keeps sending
+// from the new address.
+const PATH_CHALLENGE_INTERVAL: Duration = Duration::from_secs(1);
 
-pub(crate) struct Conn {
+struct PendingPing {
+    timestamp: u64,
+    sent: Instant,
+    reply: Option<oneshot::Sender<Result<Duration>>>,
+}
+
+// A ping sent to an address the peer seems to have moved to. Authenticated datagrams
+// can be replayed from any address, so only the peer echoing `token` proves it is
+// reachable there.
+struct PathChallenge {
     address: SocketAddr,
-    udp: Udp,
+    token: u64,
+    sent: Instant,
+}
+
+pub(crate) struct Conn {
     receive: ReceiveQueue,
     send: DatagramSender,
-    received_sender: tokio::sync::mpsc::Sender<ConnEvent>,
+    packets: PacketSender,
+    peer_addr: PeerAddr,
+    path_challenge: Option<PathChallenge>,
 
     last_ping: Instant,
+    ping_interval: Duration,
+    pings: VecDeque<PendingPing>,
+    last_received: Instant,
+    idle_timeout: Duration,
+    probed: bool,
+    rtt: RttMeter,
+    clock: ClockOffset,
+
+    bytes_received: u64,
+    datagrams_received: u64,
+    nacks_sent: u64,
+
+    closing: bool,
+    // Set once nothing may be sent any more, as the peer is gone or a new client
+    // took over its address.
+    silent: bool,
+    remote_closed: bool,
+    connection_requested: bool,
+
+    opener: Option<Opener>,
 }
 
 impl Conn {
-    pub fn new(address: SocketAddr, mtu: usize, udp: Udp, sender: mpsc::Sender<ConnEvent>) -> Self {
+    pub fn new(
+        peer_addr: PeerAddr,
+        mtu: usize,
+        udp: Udp,
+        packets: PacketSender,
+        config: &UcpConfig,
+        keys: Option<SessionKeys>,
+    ) -> Self {
+        let (sealer, opener) = match keys {
+            Some(keys) => (Some(keys.sealer), Some(keys.opener)),
+            None => (None, None),
+        };
         Self {
-            address,
-            udp: udp.clone(),
             receive: ReceiveQueue::new(),
-            send: DatagramSender::new(udp, address, mtu),
-            received_sender: sender,
+            send: DatagramSender::new(udp, peer_addr.get(), mtu, config, sealer),
+            packets,
+            peer_addr,
+            path_challenge: None,
             last_ping: Instant::now(),
+            ping_interval: config.ping_interval,
+            pings: VecDeque::new(),
+            last_received: Instant::now(),
+            idle_timeout: config.idle_timeout,
+            probed: false,
+            rtt: RttMeter::default(),
+            clock: ClockOffset::default(),
+            bytes_received: 0,
+            datagrams_received: 0,
+            nacks_sent: 0,
+            closing: false,
+            silent: false,
+            remote_closed: false,
+            connection_requested: false,
+            opener,
         }
     }
 
+    // Only datagrams that authenticate, or decode for plain sessions, are counted.
     pub async fn handle(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.silent {
+            return Ok(());
+        }
+        match &mut self.opener {
+            // Forged, corrupted and replayed datagrams are dropped silently.
+            Some(opener) => match opener.open(bytes) {
+                Some(plain) => {
+                    self.count_received(bytes.len());
+                    self.handle_plain(&plain).await
+                }
+                None => Ok(()),
+            },
+            None => {
+                self.handle_plain(bytes).await?;
+                self.count_received(bytes.len());
+                Ok(())
+            }
+        }
+    }
+
+    fn count_received(&mut self, len: usize) {
+        self.bytes_received += len as u64;
+        self.datagrams_received += 1;
+    }
+
+    // Takes a datagram from `addr`, which is challenged to become the peer's address if
+    // the datagram is authenticated and newer than anything received so far. Returns
+    // whether it was.
+    pub async fn handle_migrated(
+        &mut self,
+        bytes: &[u8],
+        addr: SocketAddr,
+    ) -> std::io::Result<bool> {
+        if self.silent {
+            return Ok(false);
+        }
+        let Some(opener) = self
+            .opener
+            .as_mut()
+            .filter(|opener| opener.is_newest(bytes))
+        else {
+            return Ok(false);
+        };
+        let Some(plain) = opener.open(bytes) else {
+            return Ok(false);
+        };
+        self.count_received(bytes.len());
+        let due = self.path_challenge.as_ref().is_none_or(|challenge| {
+            challenge.address != addr || challenge.sent.elapsed() > PATH_CHALLENGE_INTERVAL
+        });
+        if due {
+            self.challenge_path(addr).await?;
+        }
+        self.handle_plain(&plain).await?;
+        Ok(true)
+    }
+
+    async fn challenge_path(&mut self, address: SocketAddr) -> std::io::Result<()> {
+        let token = rand::random::<u64>() | PATH_CHALLENGE_FLAG;
+        let mut ping = vec![];
+        encode_syspacket(
+            ConnectedPing {
+                client_timestamp: token,
+            },
+            &mut ping,
+        )?;
+        self.send.send_lone(ping, address).await?;
+        self.path_challenge = Some(PathChallenge {
+            address,
+            token,
+            sent: Instant::now(),
+        });
+        Ok(())
+    }
+
+    async fn handle_plain(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.last_received = Instant::now();
+        self.probed = false;
         let mut reader = Cursor::new(bytes);
         let id = u8::decode(&mut reader)?;
 
@@ -53,21 +209,34 @@ impl Conn {
         Ok(())
     }
     async fn handle_datagram(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        // The session lags behind; leave the datagram unacked so the peer sends it
+        // again later.
+        if !self.packets.flush() {
+            return Ok(());
+        }
         let mut reader = Cursor::new(bytes);
         let sequence = U24::decode(&mut reader)?;
         while reader.position() < bytes.len() as u64 {
             let frame = Frame::decode(&mut reader)?;
-            let data = &reader.get_ref()
-                [reader.position() as usize..reader.position() as usize + frame.length as usize];
-            reader.set_position(reader.position() + frame.length as usize as u64);
+            let start = reader.position() as usize;
+            let Some(data) = bytes.get(start..start + frame.length as usize) else {
+                return Err(ErrorKind::InvalidData.into());
+            };
+            reader.set_position((start + frame.length as usize) as u64);
             self.handle_packet(frame, data).await?;
         }
         self.receive.received(sequence);
+        if self.remote_closed {
+            // The session is about to be dropped, so ack the notification right away.
+            self.flush_ack().await?;
+        }
         Ok(())
     }
     async fn handle_ack(&mut self, bytes: &[u8]) -> std::io::Result<()> {
         let ack: Ack = decode_syspacket(bytes)?;
-        self.send.ack(ack).await?;
+        if let Some(rtt) = self.send.ack(ack).await? {
+            self.rtt.sample(rtt);
+        }
         Ok(())
     }
     async fn handle_nack(&mut self, bytes: &[u8]) -> std::io::Result<()> {
@@ -92,29 +261,60 @@ impl Conn {
     }
 
     async fn handle_incoming_packet(&mut self, bytes: Vec<u8>) -> std::io::Result<()> {
-        let mut reader = Cursor::new(&bytes[..]);
-        match u8::decode(&mut reader)? {
+        match self.dispatch(bytes).await {
+            // The packet made it through the reliability layer, so the peer really sent it.
+            Err(e) if matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof) => {
+                self.end(DisconnectReason::ProtocolError).await
+            }
+            result => result,
+        }
+    }
+
+    async fn dispatch(&mut self, bytes: Vec<u8>) -> std::io::Result<()> {
+        let Some(&id) = bytes.first() else {
+            return Ok(());
+        };
+        match id {
             ConnectedPing::ID => {
                 let ping = decode_syspacket::<ConnectedPing>(&bytes[..])?;
                 let pong = ConnectedPong {
                     client_timestamp: ping.client_timestamp,
                     server_timestamp: time(),
                 };
-                self.send_syspacket(pong, Reliability::Reliable).await?;
+                if ping.client_timestamp & PATH_CHALLENGE_FLAG != 0 {
+                    // Acks still go to the old address until the challenge is answered,
+                    // so a queued pong could be held back by the congestion window.
+                    let mut bytes = vec![];
+                    encode_syspacket(pong, &mut bytes)?;
+                    self.send.send_lone(bytes, self.peer_addr.get()).await?;
+                } else {
+                    self.send_syspacket(pong, Reliability::Reliable).await?;
+                }
             }
-            ConnectedPong::ID => {}
+            ConnectedPong::ID => {
+                let pong = decode_syspacket::<ConnectedPong>(&bytes[..])?;
+                self.handle_pong(pong);
+            }
+            // Acking the datagram is all the answer it needs.
+            DetectLostConnections::ID => {}
             DisconnectionNotification::ID => {
-                self.disconnect().await?;
-                self.disconnected().await;
+                self.remote_closed = true;
+                self.end(DisconnectReason::RemoteClosed).await?;
+            }
+            _ => {
+                if id == ConnectionRequest::ID
+                    && decode_syspacket::<ConnectionRequest>(&bytes).is_ok()
+                {
+                    self.connection_requested = true;
+                }
+                self.packets.send(bytes)
             }
-            _ => self.notify(ConnEvent::Packet(bytes)).await,
         }
         Ok(())
     }
 
-    async fn send_bytes(&self, bytes: &[u8]) -> std::io::Result<()> {
-        self.udp.send_to(bytes, self.address).await?;
-        Ok(())
+    async fn send_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.send.send_raw(bytes).await
     }
 
     pub async fn send_syspacket<T: SystemPacket>(
@@ -128,7 +328,7 @@ impl Conn {
         Ok(())
     }
 
-    async fn send_ack(&self, seqs: (u32, u32)) -> std::io::Result<()> {
+    async fn send_ack(&mut self, seqs: (u32, u32)) -> std::io::Result<()> {
         let ack = Ack {
             ack: Acknowledge {
                 record_count: 1,
@@ -141,7 +341,7 @@ impl Conn {
         self.send_bytes(&bytes[..]).await
     }
 
-    async fn send_nack(&self, seqs: (u32, u32)) -> std::io::Result<()> {
+    async fn send_nack(&mut self, seqs: (u32, u32)) -> std::io::Result<()> {
         let nack = Nack {
             nack: Acknowledge {
                 record_count: 1,
@@ -151,23 +351,49 @@ impl Conn {
         };
         let mut bytes = vec![];
         encode_syspacket(nack, &mut bytes)?;
+        self.nacks_sent += 1;
         self.send_bytes(&bytes[..]).await
     }
 
-    pub async fn send(&mut self, bytes: &[u8], reliability: Reliability) -> std::io::Result<()> {
-        self.send.send_ref(bytes, reliability).await
+    // Packets sent after the session ended would never be delivered.
+    pub async fn send(&mut self, bytes: &[u8], reliability: Reliability) -> Result<()> {
+        if let Some(reason) = self.packets.reason() {
+            return Err(UcpError::Disconnected(reason));
+        }
+        Ok(self.send.send_ref(bytes, reliability).await?)
     }
 
     pub async fn update(&mut self) -> std::io::Result<()> {
+        self.packets.flush();
+        if self.silent {
+            return Ok(());
+        }
         self.flush_ack().await?;
         self.flush_nack().await?;
         if self.send.tick().await? {
-            self.notify(ConnEvent::Timeout).await;
+            self.closed(DisconnectReason::Timeout);
         }
         let now = Instant::now();
-        if now.duration_since(self.last_ping) > Duration::from_millis(4500) {
-            self.ping().await?;
-            self.last_ping = now;
+        self.expire_pings(now);
+        self.check_idle(now).await?;
+        if now.duration_since(self.last_ping) > self.ping_interval {
+            self.ping(None).await?;
+        }
+        Ok(())
+    }
+
+    async fn check_idle(&mut self, now: Instant) -> std::io::Result<()> {
+        if self.packets.reason().is_some() {
+            return Ok(());
+        }
+        let idle = now.duration_since(self.last_received);
+        if idle > self.idle_timeout {
+            // The peer is gone, so there is nobody to say goodbye to.
+            self.closed(DisconnectReason::IdleTimeout);
+        } else if idle > self.idle_timeout / 2 && !self.probed {
+            self.probed = true;
+            self.send_syspacket(DetectLostConnections {}, Reliability::Reliable)
+                .await?;
         }
         Ok(())
     }
@@ -186,19 +412,119 @@ impl Conn {
         Ok(())
     }
 
-    async fn ping(&mut self) -> std::io::Result<()> {
+    // `reply` receives the round-trip time once the matching pong arrives.
+    pub async fn ping(
+        &mut self,
+        reply: Option<oneshot::Sender<Result<Duration>>>,
+    ) -> std::io::Result<()> {
+        if let Some(reason) = self.packets.reason() {
+            if let Some(reply) = reply {
+                reply.send(Err(UcpError::Disconnected(reason))).ok();
+            }
+            return Ok(());
+        }
+        // Pongs are matched by timestamp, so keep it unique among pending pings.
+        let timestamp = match self.pings.back() {
+            Some(last) => std::cmp::max(time(), last.timestamp + 1),
+            None => time(),
+        };
         let ping = ConnectedPing {
-            client_timestamp: time(),
+            client_timestamp: timestamp,
         };
         self.send_syspacket(ping, Reliability::Reliable).await?;
+        let now = Instant::now();
+        self.last_ping = now;
+        self.pings.push_back(PendingPing {
+            timestamp,
+            sent: now,
+            reply,
+        });
+        while self.pings.len() > MAX_PENDING_PINGS {
+            match self.pings.iter().position(|ping| ping.reply.is_none()) {
+                Some(index) => self.pings.remove(index),
+                None => break,
+            };
+        }
         Ok(())
     }
 
-    async fn notify(&mut self, event: ConnEvent) {
-        self.received_sender
-            .send(event)
-            .await
-            .expect("failed to send event");
+    fn handle_pong(&mut self, pong: ConnectedPong) {
+        // Like a QUIC PATH_RESPONSE, the answer may come over any path.
+        let answered = self
+            .path_challenge
+            .take_if(|challenge| challenge.token == pong.client_timestamp);
+        if let Some(challenge) = answered {
+            self.send.set_address(challenge.address);
+            self.peer_addr.set(challenge.address);
+            return;
+        }
+        let Some(index) = self
+            .pings
+            .iter()
+            .position(|ping| ping.timestamp == pong.client_timestamp)
+        else {
+            return;
+        };
+        let ping = self.pings.remove(index).unwrap();
+        let rtt = ping.sent.elapsed();
+        self.rtt.sample(rtt);
+        self.clock
+            .sample(ping.timestamp, pong.server_timestamp, rtt);
+        if let Some(reply) = ping.reply {
+            reply.send(Ok(rtt)).ok();
+        }
+    }
+
+    // Pongs of a peer that ignores pings never come, so waiters are not kept forever.
+    fn expire_pings(&mut self, now: Instant) {
+        let timeout = self.send.rto().saturating_mul(PING_TIMEOUT_RTOS);
+        while let Some(ping) = self.pings.front() {
+            if now.duration_since(ping.sent) <= timeout {
+                break;
+            }
+            if let Some(reply) = self.pings.pop_front().unwrap().reply {
+                reply.send(Err(UcpError::Timeout)).ok();
+            }
+        }
+    }
+
+    fn fail_pings(&mut self, reason: DisconnectReason) {
+        for ping in self.pings.drain(..) {
+            if let Some(reply) = ping.reply {
+                reply.send(Err(UcpError::Disconnected(reason))).ok();
+            }
+        }
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.send.srtt()
+    }
+
+    pub fn rtt_stats(&self) -> Option<RttStats> {
+        self.rtt.stats()
+    }
+
+    pub fn mtu(&self) -> u16 {
+        self.send.mtu()
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr.get()
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            bytes_received: self.bytes_received,
+            datagrams_received: self.datagrams_received,
+            nacks_sent: self.nacks_sent,
+            pending_fragments: self.receive.pending_fragments(),
+            out_of_order: self.receive.out_of_order(),
+            ..self.send.stats()
+        }
+    }
+
+    pub fn clock_offset(&self) -> Option<i64> {
+        self.clock.offset()
     }
 
     async fn disconnect(&mut self) -> std::io::Result<()> {
@@ -208,8 +534,65 @@ impl Conn {
         Ok(())
     }
 
-    async fn disconnected(&mut self) {
-        self.notify(ConnEvent::Disconnected).await
+    pub async fn close(&mut self) -> std::io::Result<()> {
+        self.end(DisconnectReason::LocalClose).await
+    }
+
+    pub async fn kick(&mut self) -> std::io::Result<()> {
+        self.end(DisconnectReason::Kicked).await
+    }
+
+    pub async fn shutdown(&mut self) -> std::io::Result<()> {
+        self.end(DisconnectReason::ListenerShutdown).await
+    }
+
+    // Ends the session without a DisconnectionNotification, which would reach the
+    // client that took over the address in the middle of its handshake. Anything else
+    // still queued would too, with sequence numbers that confuse the new session.
+    pub fn replace(&mut self) {
+        self.closing = true;
+        self.silence();
+        self.closed(DisconnectReason::Kicked);
+    }
+
+    fn silence(&mut self) {
+        self.silent = true;
+        self.send.clear();
+    }
+
+    // Whether the peer got OpenConnectionReply2, as shown by its ConnectionRequest.
+    pub fn connection_requested(&self) -> bool {
+        self.connection_requested
+    }
+
+    // Says goodbye to the peer unless already done, then ends the session.
+    async fn end(&mut self, reason: DisconnectReason) -> std::io::Result<()> {
+        if !self.closing {
+            self.closing = true;
+            self.disconnect().await?;
+        }
+        self.closed(reason);
+        Ok(())
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        self.send.is_flushed()
+    }
+
+    // Only the first reason counts; the peer answering our own close is not news.
+    fn closed(&mut self, reason: DisconnectReason) {
+        if self.packets.close(reason) {
+            self.fail_pings(reason);
+        }
+        // Without a goodbye to deliver, the peer is gone and resends are wasted.
+        if !self.closing {
+            self.closing = true;
+            self.silence();
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.opener.is_some()
     }
 
     pub fn set_nodelay(&mut self, nodelay: bool) {
@@ -0,0 +1,82 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::canonical_addr;
+
+// A cookie stays valid until the secret it was made with is rotated out twice.
+const ROTATION_INTERVAL: Duration = Duration::from_secs(30);
+
+// Stateless handshake cookies: OpenConnectionReply1 hands out an HMAC of the client
+// address, which the client must echo in OpenConnectionRequest2.
+pub(crate) struct CookieJar {
+    secrets: Mutex<Secrets>,
+}
+
+struct Secrets {
+    rotated: Instant,
+    current: [u8; 32],
+    previous: [u8; 32],
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self {
+            secrets: Mutex::new(Secrets {
+                rotated: Instant::now(),
+                current: random_secret(),
+                previous: random_secret(),
+            }),
+        }
+    }
+
+    pub fn issue(&self, addr: SocketAddr) -> u32 {
+        let secrets = self.rotate();
+        cookie(&secrets.current, addr)
+    }
+
+    pub fn verify(&self, addr: SocketAddr, cookie: u32) -> bool {
+        let secrets = self.rotate();
+        self::cookie(&secrets.current, addr) == cookie
+            || self::cookie(&secrets.previous, addr) == cookie
+    }
+
+    fn rotate(&self) -> std::sync::MutexGuard<'_, Secrets> {
+        let mut secrets = self.secrets.lock().unwrap();
+        let elapsed = secrets.rotated.elapsed();
+        if elapsed >= ROTATION_INTERVAL {
+            secrets.previous = if elapsed >= ROTATION_INTERVAL * 2 {
+                random_secret()
+            } else {
+                secrets.current
+            };
+            secrets.current = random_secret();
+            secrets.rotated = Instant::now();
+        }
+        secrets
+    }
+}
+
+fn random_secret() -> [u8; 32] {
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+fn cookie(secret: &[u8; 32], addr: SocketAddr) -> u32 {
+    let addr = canonical_addr(addr);
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key size");
+    match addr.ip() {
+        IpAddr::V4(ip) => mac.update(&ip.octets()),
+        IpAddr::V6(ip) => mac.update(&ip.octets()),
+    }
+    mac.update(&addr.port().to_be_bytes());
+    let tag = mac.finalize().into_bytes();
+    u32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]])
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+// Counter prefix and Poly1305 tag added to every sealed datagram.
+pub(crate) const SEAL_OVERHEAD: usize = 8 + 16;
+
+const REPLAY_WINDOW: u64 = 64;
+
+/// Long-term X25519 key of a listener, enabling encrypted sessions.
+#[derive(Clone)]
+pub struct ServerKey {
+    secret: StaticSecret,
+}
+
+impl ServerKey {
+    pub fn generate() -> Self {
+        Self {
+            secret: StaticSecret::random_from_rng(OsRng),
+        }
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self {
+            secret: StaticSecret::from(bytes),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    /// The key clients pin with `UcpConfig::pin_server_key`.
+    pub fn public_key(&self) -> [u8; 32] {
+        PublicKey::from(&self.secret).to_bytes()
+    }
+}
+
+impl fmt::Debug for ServerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerKey")
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+// Per-handshake key; the client and the server each make one.
+pub(crate) struct EphemeralKey {
+    secret: StaticSecret,
+    pub public: [u8; 32],
+}
+
+impl EphemeralKey {
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret).to_bytes();
+        Self { secret, public }
+    }
+
+    pub fn client_keys(
+        &self,
+        server_static: [u8; 32],
+        server_ephemeral: [u8; 32],
+    ) -> Option<SessionKeys> {
+        let static_dh = self.secret.diffie_hellman(&server_static.into());
+        let ephemeral_dh = self.secret.diffie_hellman(&server_ephemeral.into());
+        if !static_dh.was_contributory() || !ephemeral_dh.was_contributory() {
+            return None;
+        }
+        let (client_to_server, server_to_client) = derive(
+            static_dh.as_bytes(),
+            ephemeral_dh.as_bytes(),
+            [self.public, server_static, server_ephemeral],
+        );
+        Some(SessionKeys::new(client_to_server, server_to_client))
+    }
+
+    pub fn server_keys(&self, server: &ServerKey, client: [u8; 32]) -> Option<SessionKeys> {
+        let static_dh = server.secret.diffie_hellman(&client.into());
+        let ephemeral_dh = self.secret.diffie_hellman(&client.into());
+        if !static_dh.was_contributory() || !ephemeral_dh.was_contributory() {
+            return None;
+        }
+        let (client_to_server, server_to_client) = derive(
+            static_dh.as_bytes(),
+            ephemeral_dh.as_bytes(),
+            [client, server.public_key(), self.public],
+        );
+        Some(SessionKeys::new(server_to_client, client_to_server))
+    }
+}
+
+// Binds both shared secrets to every public key of the handshake, then splits
+// the result into one key per direction.
+fn derive(
+    static_dh: &[u8; 32],
+    ephemeral_dh: &[u8; 32],
+    publics: [[u8; 32]; 3],
+) -> ([u8; 32], [u8; 32]) {
+    let mut master = Sha256::new();
+    master.update(b"ucp x25519-chacha20poly1305");
+    master.update(static_dh);
+    master.update(ephemeral_dh);
+    for public in publics {
+        master.update(public);
+    }
+    let master = master.finalize();
+    let direction = |label: u8| {
+        let mut key = Sha256::new();
+        key.update(master);
+        key.update([label]);
+        key.finalize().into()
+    };
+    (direction(1), direction(2))
+}
+
+pub(crate) struct SessionKeys {
+    pub sealer: Sealer,
+    pub opener: Opener,
+}
+
+impl SessionKeys {
+    fn new(send: [u8; 32], receive: [u8; 32]) -> Self {
+        Self {
+            sealer: Sealer {
+                cipher: ChaCha20Poly1305::new(Key::from_slice(&send)),
+                counter: 0,
+            },
+            opener: Opener {
+                cipher: ChaCha20Poly1305::new(Key::from_slice(&receive)),
+                highest: None,
+                window: 0,
+            },
+        }
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce.into()
+}
+
+// Every datagram, acks included, goes out as counter (u64 BE) || ciphertext || tag.
+// The counter never repeats, unlike the 24-bit datagram sequence, so it doubles as nonce.
+pub(crate) struct Sealer {
+    cipher: ChaCha20Poly1305,
+    counter: u64,
+}
+
+impl Sealer {
+    pub fn seal(&mut self, plain: &[u8]) -> Vec<u8> {
+        let counter = self.counter.to_be_bytes();
+        let payload = Payload {
+            msg: plain,
+            aad: &counter,
+        };
+        let sealed = self
+            .cipher
+            .encrypt(&nonce(self.counter), payload)
+            .expect("ChaCha20Poly1305 encryption does not fail");
+        self.counter += 1;
+        [&counter[..], &sealed].concat()
+    }
+}
+
+pub(crate) struct Opener {
+    cipher: ChaCha20Poly1305,
+    highest: Option<u64>,
+    // Bit n is set when counter `highest - n` has been opened.
+    window: u64,
+}
+
+impl Opener {
+    // None for forged, corrupted and replayed datagrams.
+    pub fn open(&mut self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return None;
+        }
+        let (counter, ciphertext) = sealed.split_at(8);
+        let counter_value = u64::from_be_bytes(counter.try_into().unwrap());
+        if self.is_replay(counter_value) {
+            return None;
+        }
+        let payload = Payload {
+            msg: ciphertext,
+            aad: counter,
+        };
+        let plain = self.cipher.decrypt(&nonce(counter_value), payload).ok()?;
+        self.mark(counter_value);
+        Some(plain)
+    }
+
+    // Whether `sealed` claims a counter above every one opened so far.
+    pub fn is_newest(&self, sealed: &[u8]) -> bool {
+        let Some(counter) = sealed.get(..8) else {
+            return false;
+        };
+        let counter = u64::from_be_bytes(counter.try_into().unwrap());
+        self.highest.is_none_or(|highest| counter > highest)
+    }
+
+    fn is_replay(&self, counter: u64) -> bool {
+        match self.highest {
+            None => false,
+            Some(highest) if counter > highest => false,
+            Some(highest) => {
+                let age = highest - counter;
+                age >= REPLAY_WINDOW || self.window & (1 << age) != 0
+            }
+        }
+    }
+
+    fn mark(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => self.window |= 1 << (highest - counter),
+            highest => {
+                let shift = highest
+                    .map(|highest| counter - highest)
+                    .unwrap_or(REPLAY_WINDOW);
+                self.window = if shift >= REPLAY_WINDOW {
+                    0
+                } else {
+                    self.window << shift
+                } | 1;
+                self.highest = Some(counter);
+            }
+        }
+    }
+}
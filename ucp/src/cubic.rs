@@ -13,7 +13,7 @@ pub(crate) struct Cubic {
     pub cwnd: u32,
     cwnd_inc: u32,
 
-    ssthresh: u32,
+    pub ssthresh: u32,
     recovery_start_time: Option<Instant>,
 }
 
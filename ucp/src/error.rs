@@ -0,0 +1,136 @@
+use std::fmt;
+
+/// Why a session ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The peer sent DisconnectionNotification.
+    RemoteClosed,
+    /// A datagram went unacked through `UcpConfig::max_resends` retransmissions.
+    Timeout,
+    /// Nothing was heard from the peer for too long.
+    IdleTimeout,
+    /// The session was closed on this side.
+    LocalClose,
+    /// The listener that accepted the session was dropped.
+    ListenerShutdown,
+    /// The listener ended the session with `UcpListener::kick`.
+    Kicked,
+    /// The peer sent a packet that could not be decoded.
+    ProtocolError,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemoteClosed => write!(f, "connection closed by remote"),
+            Self::Timeout => write!(f, "connection timed out"),
+            Self::IdleTimeout => write!(f, "connection idle for too long"),
+            Self::LocalClose => write!(f, "connection closed locally"),
+            Self::ListenerShutdown => write!(f, "listener shut down"),
+            Self::Kicked => write!(f, "kicked"),
+            Self::ProtocolError => write!(f, "protocol error"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UcpError {
+    /// The server speaks a different RakNet protocol version.
+    IncompatibleProtocol {
+        server_protocol: u8,
+    },
+    /// The server refused the connection with ConnectionBanned.
+    ConnectionBanned,
+    /// The server refused the connection with NoFreeIncomingConnections.
+    NoFreeIncomingConnections,
+    /// The server refused the connection with AlreadyConnected.
+    AlreadyConnected,
+    /// The server refused the connection with ConnectionRequestFailed.
+    ConnectionRequestFailed,
+    /// Encryption was requested but the server does not offer it.
+    EncryptionUnsupported,
+    /// The server key differs from the pinned one or is unusable.
+    UntrustedServerKey,
+    /// The server did not answer the handshake in time.
+    HandshakeTimeout,
+    /// The session has ended.
+    Disconnected(DisconnectReason),
+    /// The peer did not answer in time.
+    Timeout,
+    /// A packet could not be decoded.
+    Decode {
+        packet_id: u8,
+    },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for UcpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompatibleProtocol { server_protocol } => {
+                write!(
+                    f,
+                    "incompatible protocol version (server: {})",
+                    server_protocol
+                )
+            }
+            Self::ConnectionBanned => write!(f, "banned by server"),
+            Self::NoFreeIncomingConnections => write!(f, "server is full"),
+            Self::AlreadyConnected => write!(f, "already connected to server"),
+            Self::ConnectionRequestFailed => write!(f, "connection request failed"),
+            Self::EncryptionUnsupported => write!(f, "server does not support encryption"),
+            Self::UntrustedServerKey => write!(f, "untrusted server key"),
+            Self::HandshakeTimeout => write!(f, "handshake timed out"),
+            Self::Disconnected(reason) => reason.fmt(f),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Decode { packet_id } => write!(f, "failed to decode packet 0x{:02x}", packet_id),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for UcpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for UcpError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<UcpError> for std::io::Error {
+    fn from(e: UcpError) -> Self {
+        let kind = match e {
+            UcpError::Io(e) => return e,
+            UcpError::IncompatibleProtocol { .. }
+            | UcpError::ConnectionBanned
+            | UcpError::NoFreeIncomingConnections
+            | UcpError::AlreadyConnected
+            | UcpError::ConnectionRequestFailed
+            | UcpError::EncryptionUnsupported => std::io::ErrorKind::ConnectionRefused,
+            UcpError::UntrustedServerKey => std::io::ErrorKind::PermissionDenied,
+            UcpError::HandshakeTimeout
+            | UcpError::Timeout
+            | UcpError::Disconnected(DisconnectReason::Timeout | DisconnectReason::IdleTimeout) => {
+                std::io::ErrorKind::TimedOut
+            }
+            UcpError::Disconnected(DisconnectReason::RemoteClosed) => {
+                std::io::ErrorKind::ConnectionReset
+            }
+            UcpError::Disconnected(DisconnectReason::ProtocolError) => {
+                std::io::ErrorKind::InvalidData
+            }
+            UcpError::Disconnected(_) => std::io::ErrorKind::ConnectionAborted,
+            UcpError::Decode { .. } => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, UcpError>;
@@ -1,20 +1,53 @@
-use std::{cmp, collections::HashMap, future::Future, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    cmp,
+    collections::VecDeque,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
 
+pub use admission::{AdmissionPolicy, BanList, Decision, IpCidr, ParseCidrError};
+pub use config::{DuplicatePolicy, UcpConfig};
 use conn::Conn;
-use packet_derive::*;
+use crypto::EphemeralKey;
+pub use crypto::ServerKey;
+pub use error::{DisconnectReason, Result, UcpError};
+pub use listener::{Incoming, UcpListener};
+pub use motd::{BedrockMotd, MotdProvider, ParseMotdError};
 pub use packets::Reliability;
+use pmtu::BASE_MTU;
+pub use query::{discover, discover_at, ping, DiscoveredServer, PongInfo};
+pub use rtt::RttStats;
+pub use split::{OwnedRecvHalf, OwnedSendHalf, RecvHalf, SendHalf};
+pub use stats::ConnectionStats;
 use system_packets::*;
 use tokio::{
     net::{ToSocketAddrs, UdpSocket},
-    sync::{mpsc, Mutex, Notify},
-    time::sleep,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, watch, Mutex,
+    },
+    time::{sleep, timeout},
 };
 
+pub(crate) mod admission;
+pub(crate) mod clock;
+pub(crate) mod config;
 pub(crate) mod conn;
+pub(crate) mod cookie;
+pub(crate) mod crypto;
 pub(crate) mod cubic;
+pub(crate) mod error;
+pub(crate) mod listener;
+pub(crate) mod motd;
 pub(crate) mod packets;
+pub(crate) mod pmtu;
+pub(crate) mod query;
 pub(crate) mod receive;
+pub(crate) mod rtt;
 pub(crate) mod send;
+pub(crate) mod split;
+pub(crate) mod stats;
 pub(crate) mod system_packets;
 
 pub const PROTOCOL_VERSION: u8 = 0xA;
@@ -23,38 +56,181 @@ pub const MAX_MTU_SIZE: u16 = 1400;
 type Udp = Arc<UdpSocket>;
 type Session = Arc<Mutex<Conn>>;
 
-#[derive(Debug)]
-pub(crate) enum ConnEvent {
-    Packet(Vec<u8>),
-    Disconnected,
-    Timeout,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shutdown {
+    /// Every outstanding datagram was acked before the deadline.
+    Clean,
+    /// The deadline passed with datagrams still unacked.
+    Forced,
+}
+
+// Carries packets from a connection to its session. Closing it records why the
+// connection ended; the session reads the reason once the queued packets are drained.
+pub(crate) fn packet_channel(capacity: usize) -> (PacketSender, PacketReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let reason = Arc::new(OnceLock::new());
+    (
+        PacketSender {
+            sender: Some(sender),
+            backlog: VecDeque::new(),
+            reason: reason.clone(),
+        },
+        PacketReceiver { receiver, reason },
+    )
+}
+
+pub(crate) struct PacketSender {
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+    // Packets that did not fit into the channel yet, delivered before anything newer.
+    backlog: VecDeque<Vec<u8>>,
+    reason: Arc<OnceLock<DisconnectReason>>,
+}
+
+impl PacketSender {
+    // Never waits: connections of a listener share one driver, which a session nobody
+    // reads must not stall.
+    pub fn send(&mut self, bytes: Vec<u8>) {
+        if self.reason().is_none() {
+            self.backlog.push_back(bytes);
+            self.flush();
+        }
+    }
+
+    // Moves backlogged packets into the channel. Returns false while some are left,
+    // in which case the connection stops taking datagrams from the peer.
+    pub fn flush(&mut self) -> bool {
+        if let Some(sender) = &self.sender {
+            while let Some(bytes) = self.backlog.pop_front() {
+                match sender.try_send(bytes) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(bytes)) => {
+                        self.backlog.push_front(bytes);
+                        return false;
+                    }
+                    // The session may already be gone while the connection is closing.
+                    Err(TrySendError::Closed(_)) => self.backlog.clear(),
+                }
+            }
+        }
+        // The session sees the reason once it has drained everything before it.
+        if self.reason().is_some() {
+            self.sender = None;
+        }
+        true
+    }
+
+    // Returns false if the channel was already closed; the first reason sticks.
+    pub fn close(&mut self, reason: DisconnectReason) -> bool {
+        if self.reason.set(reason).is_err() {
+            return false;
+        }
+        self.flush();
+        true
+    }
+
+    pub fn reason(&self) -> Option<DisconnectReason> {
+        self.reason.get().copied()
+    }
+}
+
+pub(crate) struct PacketReceiver {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    reason: Arc<OnceLock<DisconnectReason>>,
+}
+
+impl PacketReceiver {
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        match self.receiver.recv().await {
+            Some(bytes) => Ok(bytes),
+            // The sender only goes away without a reason if the connection was dropped.
+            None => Err(UcpError::Disconnected(
+                self.reason().unwrap_or(DisconnectReason::LocalClose),
+            )),
+        }
+    }
+
+    pub fn reason(&self) -> Option<DisconnectReason> {
+        self.reason.get().copied()
+    }
+
+    // Lets the listener tell ended sessions apart without locking their connections.
+    pub fn reason_cell(&self) -> Arc<OnceLock<DisconnectReason>> {
+        self.reason.clone()
+    }
+}
+
+// Current address of the peer, shared by a connection and its session. Only a
+// listener migrating the connection changes it.
+#[derive(Clone)]
+pub(crate) struct PeerAddr(Arc<RwLock<SocketAddr>>);
+
+impl PeerAddr {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self(Arc::new(RwLock::new(addr)))
+    }
+
+    pub fn get(&self) -> SocketAddr {
+        *self.0.read().unwrap()
+    }
+
+    pub fn set(&self, addr: SocketAddr) {
+        *self.0.write().unwrap() = addr;
+    }
+}
+
+// Both ends of a session, as settled by the handshake.
+#[derive(Clone)]
+pub(crate) struct Endpoints {
+    peer_addr: PeerAddr,
+    peer_guid: u64,
+    local_addr: SocketAddr,
 }
 
 pub struct UcpSession {
-    receiver: mpsc::Receiver<ConnEvent>,
-    addr: SocketAddr,
+    receiver: PacketReceiver,
+    endpoints: Endpoints,
+    // This side's address as the peer sees it, from ConnectionRequestAccepted or
+    // NewIncomingConnections.
+    observed_addr: Option<SocketAddr>,
     conn: Session,
+    tick_interval: Duration,
+    close_timeout: Duration,
+    ping_timeout: Duration,
 
-    drop_notifyor: Arc<Notify>,
-    drop_sender: Option<mpsc::Sender<SocketAddr>>,
+    drop_guard: Arc<DropGuard>,
+}
+
+// Tears the session down once the session, or every half of it, is dropped.
+struct DropGuard {
+    addr: PeerAddr,
+    // Dropping it stops the session's tasks.
+    _shutdown: watch::Sender<()>,
+    // Tells the listener which session went away, as a newer one may hold the address.
+    conn: Session,
+    sender: Option<mpsc::Sender<(SocketAddr, Session)>>,
 }
 
 impl UcpSession {
-    pub async fn connect(
+    pub async fn connect(local: impl ToSocketAddrs, remote: SocketAddr, guid: u64) -> Result<Self> {
+        Self::connect_with(local, remote, guid, UcpConfig::default()).await
+    }
+
+    pub async fn connect_with(
         local: impl ToSocketAddrs,
         remote: SocketAddr,
         guid: u64,
-    ) -> std::io::Result<Self> {
-        let udp: Udp = Arc::new(UdpSocket::bind(local).await?);
+        config: UcpConfig,
+    ) -> Result<Self> {
+        let udp: Udp = Arc::new(bind_socket(local).await?);
+        let remote = peer_addr_for(udp.local_addr()?, remote);
+        let ephemeral = config.use_encryption.then(EphemeralKey::generate);
 
         let reply1: OpenConnectionReply1 = async {
-            for count in 0..12 {
-                let mtu = match count / 4 {
-                    0 => 1496,
-                    1 => 1204,
-                    2 => 584,
-                    _ => 0,
-                };
+            let attempts = config
+                .mtu_ladder
+                .iter()
+                .flat_map(|mtu| std::iter::repeat_n(*mtu, config.handshake_attempts));
+            for mtu in attempts {
                 let ocrequest1 = OpenConnectionRequest1 {
                     magic: (),
                     protocol_version: PROTOCOL_VERSION,
@@ -66,12 +242,12 @@ impl UcpSession {
 
                 let decode_ocreply1 = async {
                     loop {
-                        let mut v = [0u8; 2048];
+                        let mut v = vec![0u8; config.max_mtu_size as usize];
                         let (size, src) = udp.recv_from(&mut v).await?;
 
                         if src == remote {
-                            let reply: OpenConnectionReply1 = decode_syspacket(&v[..size])?;
-                            return Ok(reply);
+                            check_rejection(&v[..size])?;
+                            return decode_packet::<OpenConnectionReply1>(&v[..size]);
                         }
                     }
                 };
@@ -80,33 +256,51 @@ impl UcpSession {
                     r = decode_ocreply1 => {
                         return r
                     },
-                    _ = sleep(Duration::from_millis(500)) => {}
+                    _ = sleep(config.handshake_retry_interval) => {}
                 }
             }
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotConnected,
-                format!("Failed to connect to {}", remote),
-            ))
+            Err(UcpError::HandshakeTimeout)
         }
         .await?;
+        // Anything below the base MTU leaves no room for a frame; only a broken or
+        // hostile server offers it.
+        if reply1.mtu_size < BASE_MTU {
+            return Err(UcpError::Decode {
+                packet_id: OpenConnectionReply1::ID,
+            });
+        }
+
+        let server_key = match (&ephemeral, reply1.server_key) {
+            (None, _) => None,
+            (Some(_), None) => return Err(UcpError::EncryptionUnsupported),
+            (Some(_), Some(key)) => {
+                if config.pinned_server_key.is_some_and(|pinned| pinned != key) {
+                    return Err(UcpError::UntrustedServerKey);
+                }
+                Some(key)
+            }
+        };
 
         let reply2: OpenConnectionReply2 = async {
-            for _ in 0..4 {
+            for _ in 0..config.handshake_attempts {
                 let decode_ocreply2 = async {
                     let ocrequest2 = OpenConnectionRequest2 {
                         magic: (),
-                        address: remote,
-                        mtu: cmp::min(reply1.mtu_size, MAX_MTU_SIZE),
+                        cookie: reply1.cookie,
+                        client_key: ephemeral.as_ref().map(|key| key.public),
+                        address: canonical_addr(remote),
+                        mtu: cmp::min(reply1.mtu_size, config.max_mtu_size),
                         guid,
                     };
                     let mut bytes = vec![];
                     encode_syspacket(ocrequest2, &mut bytes)?;
                     udp.send_to(&bytes, remote).await?;
                     loop {
-                        let mut v = [0u8; 2048];
+                        let mut v = vec![0u8; config.max_mtu_size as usize];
                         let (size, src) = udp.recv_from(&mut v).await?;
                         if src == remote {
-                            return decode_syspacket::<OpenConnectionReply2>(&v[..size]);
+                            check_rejection(&v[..size])?;
+                            return decode_packet::<OpenConnectionReply2>(&v[..size]);
                         }
                     }
                 };
@@ -115,71 +309,100 @@ impl UcpSession {
                     r = decode_ocreply2 => {
                         return r
                     },
-                    _ = sleep(Duration::from_millis(500)) => {}
+                    _ = sleep(config.handshake_retry_interval) => {}
                 }
             }
-            Err(std::io::Error::new(
-                std::io::ErrorKind::NotConnected,
-                format!("Failed to connect to {}", remote),
-            ))
+            Err(UcpError::HandshakeTimeout)
         }
         .await?;
+        if reply2.mtu < BASE_MTU {
+            return Err(UcpError::Decode {
+                packet_id: OpenConnectionReply2::ID,
+            });
+        }
 
-        let (s, r) = mpsc::channel(128);
+        let keys = match (ephemeral, server_key) {
+            (Some(ephemeral), Some(server_key)) => {
+                let server_ephemeral = reply2.server_key.ok_or(UcpError::EncryptionUnsupported)?;
+                let keys = ephemeral.client_keys(server_key, server_ephemeral);
+                Some(keys.ok_or(UcpError::UntrustedServerKey)?)
+            }
+            _ => None,
+        };
+        let use_encryption = keys.is_some();
+
+        let peer_addr = PeerAddr::new(remote);
+        let (s, r) = packet_channel(config.session_channel_capacity);
         let conn = Arc::new(Mutex::new(Conn::new(
-            remote,
-            cmp::min(reply2.mtu, MAX_MTU_SIZE) as usize,
+            peer_addr.clone(),
+            cmp::min(reply2.mtu, config.max_mtu_size) as usize,
             udp.clone(),
             s,
+            &config,
+            keys,
         )));
 
-        let mut session = Self::init_with_conn(conn, r, remote, None, Some(udp));
+        let endpoints = Endpoints {
+            peer_addr,
+            peer_guid: reply1.guid,
+            local_addr: udp.local_addr()?,
+        };
+        let mut session = Self::init_with_conn(conn, r, endpoints, None, Some(udp), &config);
 
         let request = ConnectionRequest {
             guid,
             time: time(),
-            use_encryption: false,
+            use_encryption,
         };
         session
             .send_syspacket(request, Reliability::ReliableOrdered)
             .await?;
-        loop {
-            let got = session.recv().await?;
-            if got[0] == ConnectionRequestAccepted::ID {
-                let accepted: ConnectionRequestAccepted =
-                    decode_syspacket::<ConnectionRequestAccepted>(&got)?;
-                let new_incoming = NewIncomingConnections {
-                    server_address: remote,
-                    request_timestamp: accepted.request_timestamp,
-                    accepted_timestamp: accepted.accepted_timestamp,
-                };
-                session
-                    .send_syspacket(new_incoming, Reliability::ReliableOrdered)
-                    .await?;
-                return Ok(session);
+        let accepted = async {
+            loop {
+                let got = session.recv().await?;
+                check_rejection(&got)?;
+                if got[0] == ConnectionRequestAccepted::ID {
+                    return decode_packet::<ConnectionRequestAccepted>(&got);
+                }
             }
-        }
+        };
+        let attempts = u32::try_from(config.handshake_attempts).unwrap_or(u32::MAX);
+        let deadline = config.handshake_retry_interval.saturating_mul(attempts);
+        let accepted = timeout(deadline, accepted)
+            .await
+            .map_err(|_| UcpError::HandshakeTimeout)??;
+        session.observed_addr = Some(accepted.client_address);
+        let new_incoming = NewIncomingConnections {
+            server_address: canonical_addr(remote),
+            request_timestamp: accepted.request_timestamp,
+            accepted_timestamp: accepted.accepted_timestamp,
+        };
+        session
+            .send_syspacket(new_incoming, Reliability::ReliableOrdered)
+            .await?;
+        Ok(session)
     }
 
     fn init_with_conn(
         conn: Session,
-        receiver: mpsc::Receiver<ConnEvent>,
-        addr: SocketAddr,
-        sender: Option<mpsc::Sender<SocketAddr>>,
+        receiver: PacketReceiver,
+        endpoints: Endpoints,
+        sender: Option<mpsc::Sender<(SocketAddr, Session)>>,
         udp: Option<Udp>,
+        config: &UcpConfig,
     ) -> Self {
         let ticker = conn.clone();
-        let n1 = Arc::new(Notify::new());
-        let n2 = n1.clone();
+        let (shutdown, mut ticker_shutdown) = watch::channel(());
+        let tick_interval = config.tick_interval;
         tokio::spawn(async move {
             loop {
                 let tick = async {
-                    sleep(Duration::from_millis(50)).await;
+                    sleep(tick_interval).await;
                     ticker.lock().await.update().await.unwrap();
                 };
                 tokio::select! {
                     _ = tick => {},
-                    _ = n2.notified() => {
+                    _ = ticker_shutdown.changed() => {
                         break;
                     }
                 }
@@ -187,12 +410,13 @@ impl UcpSession {
         });
 
         if let Some(udp) = udp {
-            let n3 = n1.clone();
+            let mut reader_shutdown = shutdown.subscribe();
             let conn2 = conn.clone();
-            let address = addr;
+            let address = endpoints.peer_addr.get();
+            let buffer_len = config.max_mtu_size as usize;
             tokio::spawn(async move {
+                let mut v = vec![0u8; buffer_len];
                 loop {
-                    let mut v = [0u8; 1500];
                     tokio::select! {
                         res = udp.recv_from(&mut v) => {
                             let (size,src) = match res {
@@ -203,7 +427,7 @@ impl UcpSession {
                                 conn2.lock().await.handle(&v[..size]).await.unwrap_or_default();
                             }
                         },
-                        _ = n3.notified() => {
+                        _ = reader_shutdown.changed() => {
                             break;
                         }
                     }
@@ -213,35 +437,59 @@ impl UcpSession {
 
         Self {
             receiver,
-            addr,
-            conn,
-            drop_notifyor: n1,
-            drop_sender: sender,
+            endpoints: endpoints.clone(),
+            observed_addr: None,
+            conn: conn.clone(),
+            tick_interval,
+            close_timeout: config.close_timeout,
+            ping_timeout: config.idle_timeout,
+            drop_guard: Arc::new(DropGuard {
+                addr: endpoints.peer_addr,
+                _shutdown: shutdown,
+                conn,
+                sender,
+            }),
         }
     }
 
-    pub async fn recv(&mut self) -> std::io::Result<Vec<u8>> {
-        loop {
-            match self.receiver.recv().await {
-                Some(ConnEvent::Packet(bytes)) => return Ok(bytes),
-                Some(ConnEvent::Disconnected) => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::ConnectionReset,
-                        "Connection closed",
-                    ))
-                }
-                Some(ConnEvent::Timeout) => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::TimedOut,
-                        "Connection timeout",
-                    ))
-                }
-                None => {}
-            }
-        }
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.receiver.recv().await
     }
 
-    pub async fn send(&self, bytes: &[u8], reliability: Reliability) -> std::io::Result<()> {
+    /// The peer's current address, which may change if the listener allows
+    /// `UcpConfig::connection_migration`. IPv4 peers of a dual-stack socket are
+    /// reported as plain IPv4 addresses.
+    pub fn peer_addr(&self) -> SocketAddr {
+        canonical_addr(self.endpoints.peer_addr.get())
+    }
+
+    /// GUID the peer sent in its handshake.
+    pub fn peer_guid(&self) -> u64 {
+        self.endpoints.peer_guid
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.endpoints.local_addr
+    }
+
+    /// This side's address as reported by the peer during the handshake, which shows
+    /// the public address when behind a NAT.
+    pub fn observed_addr(&self) -> Option<SocketAddr> {
+        self.observed_addr
+    }
+
+    /// Current MTU of the path, including the UDP and IP headers.
+    pub async fn mtu(&self) -> u16 {
+        self.conn.lock().await.mtu()
+    }
+
+    /// Why the session ended, once it has. Packets received before that are still
+    /// returned by `recv` first.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.receiver.reason()
+    }
+
+    pub async fn send(&self, bytes: &[u8], reliability: Reliability) -> Result<()> {
         self.conn.lock().await.send(bytes, reliability).await
     }
 
@@ -257,6 +505,73 @@ impl UcpSession {
             .await
     }
 
+    pub async fn close(self) -> Result<Shutdown> {
+        self.conn.lock().await.close().await?;
+        let flushed = async {
+            while !self.conn.lock().await.is_flushed() {
+                sleep(self.tick_interval).await;
+            }
+        };
+        match timeout(self.close_timeout, flushed).await {
+            Ok(()) => Ok(Shutdown::Clean),
+            Err(_) => Ok(Shutdown::Forced),
+        }
+    }
+
+    pub async fn is_encrypted(&self) -> bool {
+        self.conn.lock().await.is_encrypted()
+    }
+
+    pub async fn stats(&self) -> ConnectionStats {
+        self.conn.lock().await.stats()
+    }
+
+    /// Smoothed round-trip time, once a datagram has been acked.
+    pub async fn rtt(&self) -> Option<Duration> {
+        self.conn.lock().await.rtt()
+    }
+
+    /// Half the smoothed round-trip time.
+    pub async fn latency(&self) -> Option<Duration> {
+        self.rtt().await.map(|rtt| rtt / 2)
+    }
+
+    pub async fn rtt_stats(&self) -> Option<RttStats> {
+        self.conn.lock().await.rtt_stats()
+    }
+
+    /// Sends a ConnectedPing and waits for the pong, failing with `UcpError::Timeout`
+    /// if it does not come within `UcpConfig::idle_timeout`.
+    pub async fn ping(&self) -> Result<Duration> {
+        let (sender, receiver) = oneshot::channel();
+        self.conn.lock().await.ping(Some(sender)).await?;
+        match timeout(self.ping_timeout, receiver).await {
+            Ok(Ok(rtt)) => rtt,
+            Ok(Err(_)) => Err(UcpError::Disconnected(
+                self.disconnect_reason()
+                    .unwrap_or(DisconnectReason::LocalClose),
+            )),
+            Err(_) => Err(UcpError::Timeout),
+        }
+    }
+
+    /// How far the peer's clock is ahead of ours, in milliseconds, estimated from pongs.
+    pub async fn clock_offset(&self) -> Option<i64> {
+        self.conn.lock().await.clock_offset()
+    }
+
+    /// The peer's current Unix time in milliseconds.
+    pub async fn remote_time_now(&self) -> Option<u64> {
+        let offset = self.clock_offset().await?;
+        Some(time().saturating_add_signed(offset))
+    }
+
+    /// Converts a Unix timestamp in milliseconds from the peer's clock to ours.
+    pub async fn to_local_time(&self, remote_ts: u64) -> Option<u64> {
+        let offset = self.clock_offset().await?;
+        Some(remote_ts.saturating_add_signed(-offset))
+    }
+
     pub async fn set_nodelay(&self, nodelay: bool) {
         self.conn.lock().await.set_nodelay(nodelay);
     }
@@ -266,182 +581,94 @@ impl UcpSession {
     }
 }
 
-impl Drop for UcpSession {
-    fn drop(&mut self) {
-        self.drop_notifyor.notify_one();
-        if let Some(s) = self.drop_sender.clone() {
-            let addr = self.addr;
-            tokio::spawn(async move {
-                s.send(addr).await.unwrap();
-            });
+// Turns the replies a server uses to refuse a connection into errors.
+fn check_rejection(bytes: &[u8]) -> Result<()> {
+    match bytes.first() {
+        Some(&IncompatibleProtocolVersion::ID) => {
+            let reply: IncompatibleProtocolVersion = decode_packet(bytes)?;
+            Err(UcpError::IncompatibleProtocol {
+                server_protocol: reply.server_protocol,
+            })
         }
+        Some(&ConnectionBanned::ID) => Err(UcpError::ConnectionBanned),
+        Some(&NoFreeIncomingConnections::ID) => Err(UcpError::NoFreeIncomingConnections),
+        Some(&AlreadyConnected::ID) => Err(UcpError::AlreadyConnected),
+        Some(&ConnectionRequestFailed::ID) => Err(UcpError::ConnectionRequestFailed),
+        _ => Ok(()),
     }
 }
 
-async fn into_session(mut session: UcpSession) -> std::io::Result<UcpSession> {
-    loop {
-        let got = session.recv().await?;
-        match got[0] {
-            ConnectionRequest::ID => {
-                let request: ConnectionRequest = decode_syspacket(&got)?;
-                let accept = ConnectionRequestAccepted {
-                    client_address: session.addr,
-                    system_index: 0,
-                    request_timestamp: request.time,
-                    accepted_timestamp: time(),
-                };
-                session
-                    .send_syspacket(accept, Reliability::ReliableOrdered)
-                    .await?;
-            }
-            NewIncomingConnections::ID => return Ok(session),
-            _ => {}
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(s) = self.sender.clone() {
+            let addr = self.addr.get();
+            let conn = self.conn.clone();
+            tokio::spawn(async move {
+                // The listener may already be gone.
+                s.send((addr, conn)).await.ok();
+            });
         }
     }
 }
 
-pub struct UcpListener {
-    socket: Udp,
-    guid: u64,
-    title: String,
-    conns: HashMap<SocketAddr, Session>,
-    drop_receiver: mpsc::Receiver<SocketAddr>,
-    drop_sender: mpsc::Sender<SocketAddr>,
+pub(crate) fn time() -> u64 {
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    time as u64
 }
 
-impl UcpListener {
-    pub fn get_raw_socket(&self) -> Arc<UdpSocket> {
-        self.socket.clone()
-    }
-    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
-        self.socket.local_addr()
-    }
-    pub async fn bind(addr: impl ToSocketAddrs, guid: u64, title: String) -> std::io::Result<Self> {
-        let (s, r) = mpsc::channel(32);
-        Ok(Self {
-            socket: Arc::new(UdpSocket::bind(addr).await?),
-            guid,
-            title,
-            conns: HashMap::new(),
-            drop_receiver: r,
-            drop_sender: s,
-        })
-    }
-    pub async fn accept(
-        &mut self,
-    ) -> std::io::Result<impl Future<Output = Result<UcpSession, std::io::Error>>> {
-        loop {
-            let mut v = [0u8; 2048];
-            let (size, src) = tokio::select! {
-                rs = self.socket.recv_from(&mut v) => {rs},
-                addr = self.drop_receiver.recv() => {
-                    self.conns.remove(&addr.unwrap());
-                    continue;
-                }
-            }?;
-
-            if self.conns.contains_key(&src) {
-                self.conns
-                    .get(&src)
-                    .unwrap()
-                    .lock()
-                    .await
-                    .handle(&v[..size])
-                    .await?;
-            } else {
-                let mut reader = std::io::Cursor::new(&v[..size]);
-                match u8::decode(&mut reader)? {
-                    UnconnectedPing::ID => self.handle_ping(&v[..size], src).await?,
-                    OpenConnectionRequest1::ID => self.handle_ocrequest1(&v[..size], src).await?,
-                    OpenConnectionRequest2::ID => {
-                        let (conn, r) = self.handle_ocrequest2(&v[..size], src).await?;
-                        return Ok(into_session(UcpSession::init_with_conn(
-                            conn,
-                            r,
-                            src,
-                            Some(self.drop_sender.clone()),
-                            None,
-                        )));
-                    }
-                    _ => {}
-                }
+// Binds a UDP socket. IPv6 sockets are dual-stack so that `[::]` also accepts IPv4 peers.
+pub(crate) async fn bind_socket(addr: impl ToSocketAddrs) -> std::io::Result<UdpSocket> {
+    let mut last_err = None;
+    for addr in tokio::net::lookup_host(addr).await? {
+        let socket = match socket2::Socket::new(
+            socket2::Domain::for_address(addr),
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        ) {
+            Ok(socket) => socket,
+            Err(e) => {
+                last_err = Some(e);
+                continue;
             }
+        };
+        if addr.is_ipv6() {
+            socket.set_only_v6(false)?;
+        }
+        socket.set_nonblocking(true)?;
+        if let Err(e) = socket.bind(&addr.into()) {
+            last_err = Some(e);
+            continue;
         }
+        return UdpSocket::from_std(socket.into());
     }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "could not resolve to any address",
+        )
+    }))
+}
 
-    async fn handle_ping(&self, v: &[u8], src: SocketAddr) -> std::io::Result<()> {
-        let packet: UnconnectedPing = decode_syspacket(v)?;
-        let pong = UnconnectedPong {
-            time: packet.time_stamp,
-            guid: self.guid,
-            magic: (),
-            motd: self.title.clone(),
-        };
-        let mut bytes = vec![];
-        encode_syspacket(pong, &mut bytes)?;
-        self.socket.send_to(&bytes[..], src).await?;
-        Ok(())
-    }
-
-    async fn handle_ocrequest1(&self, v: &[u8], src: SocketAddr) -> std::io::Result<()> {
-        let packet: OpenConnectionRequest1 = decode_syspacket(v)?;
-
-        if packet.protocol_version != PROTOCOL_VERSION {
-            let reply = IncompatibleProtocolVersion {
-                server_protocol: PROTOCOL_VERSION,
-                magic: (),
-                server_guid: self.guid,
-            };
-            let mut bytes = vec![];
-            encode_syspacket(reply, &mut bytes)?;
-            self.socket.send_to(&bytes[..], src).await?;
-            return Ok(());
-        }
-        let reply = OpenConnectionReply1 {
-            magic: (),
-            guid: self.guid,
-            use_encryption: false,
-            mtu_size: cmp::min(packet.mtu_size, MAX_MTU_SIZE),
-        };
-        let mut bytes = vec![];
-        encode_syspacket(reply, &mut bytes)?;
-        self.socket.send_to(&bytes[..], src).await?;
-        Ok(())
-    }
-
-    async fn handle_ocrequest2(
-        &mut self,
-        v: &[u8],
-        src: SocketAddr,
-    ) -> std::io::Result<(Session, mpsc::Receiver<ConnEvent>)> {
-        let packet: OpenConnectionRequest2 = decode_syspacket(v)?;
-        let mtu = cmp::min(packet.mtu, MAX_MTU_SIZE);
-        let reply = OpenConnectionReply2 {
-            magic: (),
-            guid: self.guid,
-            address: src,
-            mtu,
-            use_encryption: false,
-        };
-        let mut bytes = vec![];
-        encode_syspacket(reply, &mut bytes)?;
-        self.socket.send_to(&bytes[..], src).await?;
-        let (s, r) = mpsc::channel(128);
-        let session = Arc::new(Mutex::new(Conn::new(
-            src,
-            mtu as usize,
-            self.socket.clone(),
-            s,
-        )));
-        self.conns.insert(src, session.clone());
-        Ok((session, r))
+// IPv4 peers of a dual-stack socket show up as IPv4-mapped IPv6 addresses.
+pub(crate) fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+            None => addr,
+        },
+        SocketAddr::V4(_) => addr,
     }
 }
 
-pub(crate) fn time() -> u64 {
-    let time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
-    time as u64
+// Address to use on a socket bound to `local` to reach `remote`.
+pub(crate) fn peer_addr_for(local: SocketAddr, remote: SocketAddr) -> SocketAddr {
+    match (local, remote) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => {
+            SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port())
+        }
+        _ => remote,
+    }
 }
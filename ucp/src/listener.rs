@@ -0,0 +1,698 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, OnceLock, RwLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use packet_derive::*;
+use tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{mpsc, Mutex, Notify},
+    time::timeout,
+};
+use tokio_stream::Stream;
+
+use crate::{
+    admission::Admission, bind_socket, canonical_addr, conn::Conn, cookie::CookieJar,
+    crypto::EphemeralKey, packet_channel, peer_addr_for, pmtu::BASE_MTU, system_packets::*, time,
+    AdmissionPolicy, BanList, Decision, DisconnectReason, DuplicatePolicy, Endpoints, IpCidr,
+    MotdProvider, PacketReceiver, PeerAddr, Reliability, Result, ServerKey, Session, UcpConfig,
+    UcpError, UcpSession, Udp, PROTOCOL_VERSION,
+};
+
+pub struct UcpListener {
+    shared: Arc<Shared>,
+    accepted: mpsc::Receiver<UcpSession>,
+    shutdown: Arc<Notify>,
+}
+
+/// Stream of incoming sessions, see [`UcpListener::incoming`].
+pub struct Incoming<'a> {
+    listener: &'a mut UcpListener,
+}
+
+// State shared between the listener handle, its driver task and pending handshakes.
+struct Shared {
+    socket: Udp,
+    guid: u64,
+    config: UcpConfig,
+    title: RwLock<String>,
+    motd_provider: RwLock<Option<Arc<dyn MotdProvider>>>,
+    conns: std::sync::Mutex<Conns>,
+    admission: Admission,
+    cookies: Option<CookieJar>,
+}
+
+// Live sessions by address, indexed by client GUID.
+#[derive(Default)]
+struct Conns {
+    by_addr: HashMap<SocketAddr, ConnEntry>,
+    by_guid: HashMap<u64, SocketAddr>,
+}
+
+struct ConnEntry {
+    session: Session,
+    guid: u64,
+    // OpenConnectionReply2, sent again if the client repeats its request.
+    reply: Vec<u8>,
+    // Set once the session ended; it stays here until the application drops it.
+    reason: Arc<OnceLock<DisconnectReason>>,
+}
+
+impl ConnEntry {
+    fn is_live(&self) -> bool {
+        self.reason.get().is_none()
+    }
+}
+
+impl Conns {
+    fn get(&self, addr: SocketAddr) -> Option<Session> {
+        self.by_addr.get(&addr).map(|entry| entry.session.clone())
+    }
+
+    fn get_live(&self, addr: SocketAddr) -> Option<Session> {
+        let entry = self.by_addr.get(&addr).filter(|entry| entry.is_live())?;
+        Some(entry.session.clone())
+    }
+
+    fn insert(&mut self, addr: SocketAddr, entry: ConnEntry) {
+        self.by_guid.insert(entry.guid, addr);
+        if let Some(old) = self.by_addr.insert(addr, entry) {
+            self.unindex(addr, old.guid);
+        }
+    }
+
+    // Removes `session`, last seen at `addr`. A newer session may have taken that
+    // address, or the session may have migrated away from it.
+    fn remove(&mut self, addr: SocketAddr, session: &Session) {
+        let is_session = |entry: &ConnEntry| Arc::ptr_eq(&entry.session, session);
+        let addr = if self.by_addr.get(&addr).is_some_and(is_session) {
+            addr
+        } else {
+            match self.by_addr.iter().find(|(_, entry)| is_session(entry)) {
+                Some((addr, _)) => *addr,
+                None => return,
+            }
+        };
+        if let Some(entry) = self.by_addr.remove(&addr) {
+            self.unindex(addr, entry.guid);
+        }
+    }
+
+    fn rebind(&mut self, from: SocketAddr, to: SocketAddr) {
+        if let Some(entry) = self.by_addr.remove(&from) {
+            self.unindex(from, entry.guid);
+            self.insert(to, entry);
+        }
+    }
+
+    // Sessions at other ports of the IP of `addr`.
+    fn same_ip(&self, addr: SocketAddr) -> Vec<(SocketAddr, Session)> {
+        let ip = canonical_addr(addr).ip();
+        self.by_addr
+            .iter()
+            .filter(|(other, _)| **other != addr && canonical_addr(**other).ip() == ip)
+            .map(|(other, entry)| (*other, entry.session.clone()))
+            .collect()
+    }
+
+    fn unindex(&mut self, addr: SocketAddr, guid: u64) {
+        if self.by_guid.get(&guid) == Some(&addr) {
+            self.by_guid.remove(&guid);
+        }
+    }
+
+    // The session `guid` started at `addr`, with the reply it was given.
+    fn pending(&self, addr: SocketAddr, guid: u64) -> Option<(Session, Vec<u8>)> {
+        let entry = self.by_addr.get(&addr).filter(|entry| entry.guid == guid)?;
+        Some((entry.session.clone(), entry.reply.clone()))
+    }
+
+    // Live sessions at `addr` or with `guid`.
+    fn duplicates(&self, addr: SocketAddr, guid: u64) -> Vec<(SocketAddr, Session)> {
+        let other = self.by_guid.get(&guid).filter(|other| **other != addr);
+        [Some(addr), other.copied()]
+            .into_iter()
+            .flatten()
+            .filter_map(|addr| Some((addr, self.get_live(addr)?)))
+            .collect()
+    }
+
+    // Removes the ended session at `addr`, if there is one.
+    fn take_ended(&mut self, addr: SocketAddr) -> Option<Session> {
+        let entry = self.by_addr.get(&addr).filter(|entry| !entry.is_live())?;
+        let session = entry.session.clone();
+        self.remove(addr, &session);
+        Some(session)
+    }
+
+    fn live_count(&self) -> usize {
+        self.by_addr
+            .values()
+            .filter(|entry| entry.is_live())
+            .count()
+    }
+
+    fn sessions(&self) -> Vec<Session> {
+        self.by_addr
+            .values()
+            .map(|entry| entry.session.clone())
+            .collect()
+    }
+}
+
+impl UcpListener {
+    pub fn get_raw_socket(&self) -> Arc<UdpSocket> {
+        self.shared.socket.clone()
+    }
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.shared.socket.local_addr()
+    }
+    pub async fn bind(addr: impl ToSocketAddrs, guid: u64, title: String) -> std::io::Result<Self> {
+        Self::bind_with(addr, guid, title, UcpConfig::default()).await
+    }
+    pub async fn bind_with(
+        addr: impl ToSocketAddrs,
+        guid: u64,
+        title: String,
+        config: UcpConfig,
+    ) -> std::io::Result<Self> {
+        let (accepted_sender, accepted) = mpsc::channel(config.listener_channel_capacity);
+        // Encrypted sessions ride on the cookie exchange, as in RakNet's secure mode.
+        let cookies =
+            (config.handshake_cookies || config.server_key.is_some()).then(CookieJar::new);
+        let shared = Arc::new(Shared {
+            socket: Arc::new(bind_socket(addr).await?),
+            guid,
+            config,
+            title: RwLock::new(title),
+            motd_provider: RwLock::new(None),
+            conns: std::sync::Mutex::new(Conns::default()),
+            admission: Admission::default(),
+            cookies,
+        });
+        let shutdown = Arc::new(Notify::new());
+        tokio::spawn(drive(shared.clone(), accepted_sender, shutdown.clone()));
+        Ok(Self {
+            shared,
+            accepted,
+            shutdown,
+        })
+    }
+
+    /// Waits for the next session that completed its handshake. Sessions are driven in
+    /// the background, whether or not this is called.
+    ///
+    /// Handshakes that fail or time out are dropped, as any peer can cause them; an
+    /// error means the listener itself stopped.
+    pub async fn accept(&mut self) -> Result<UcpSession> {
+        self.accepted
+            .recv()
+            .await
+            .ok_or_else(|| UcpError::Io(std::io::Error::other("listener driver stopped")))
+    }
+
+    /// Sessions that completed ConnectionRequest/NewIncomingConnections. Handshakes run
+    /// concurrently, each bounded by `UcpConfig::accept_timeout`; failed ones are dropped.
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    pub fn title(&self) -> String {
+        self.shared.title.read().unwrap().clone()
+    }
+
+    pub fn set_title(&self, title: String) {
+        *self.shared.title.write().unwrap() = title;
+    }
+
+    /// Answers unconnected pings with the MOTD from `provider`, which takes precedence
+    /// over the title.
+    pub fn set_motd_provider(&self, provider: impl MotdProvider + 'static) {
+        *self.shared.motd_provider.write().unwrap() = Some(Arc::new(provider));
+    }
+
+    /// Sessions that have not ended yet, counted against `UcpConfig::max_connections`.
+    pub fn connection_count(&self) -> usize {
+        self.shared.connection_count()
+    }
+
+    pub fn set_admission_policy(&self, policy: impl AdmissionPolicy + 'static) {
+        *self.shared.admission.policy.write().unwrap() = Some(Arc::new(policy));
+    }
+
+    /// Shared handle to the ban list, so bans can be managed from other tasks.
+    pub fn ban_list(&self) -> Arc<BanList> {
+        self.shared.admission.bans.clone()
+    }
+
+    pub fn ban(&self, cidr: impl Into<IpCidr>, duration: Option<Duration>) {
+        self.shared.admission.bans.ban(cidr, duration);
+    }
+
+    pub fn unban(&self, cidr: impl Into<IpCidr>) -> bool {
+        self.shared.admission.bans.unban(cidr)
+    }
+
+    /// Sends DisconnectionNotification to the session at `addr` and ends it. Returns
+    /// false if there is none, or it already ended. IPv4 peers of a dual-stack listener may be given as
+    /// plain IPv4 addresses, as `UcpSession::peer_addr` reports them.
+    pub async fn kick(&self, addr: SocketAddr) -> Result<bool> {
+        let addr = peer_addr_for(self.local_addr()?, canonical_addr(addr));
+        let conn = self.shared.conns.lock().unwrap().get_live(addr);
+        match conn {
+            Some(conn) => {
+                conn.lock().await.kick().await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl Stream for Incoming<'_> {
+    type Item = Result<UcpSession>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.listener
+            .accepted
+            .poll_recv(cx)
+            .map(|session| session.map(Ok))
+    }
+}
+
+impl Drop for UcpListener {
+    fn drop(&mut self) {
+        self.shutdown.notify_one();
+    }
+}
+
+// Reads the socket for as long as the listener lives, so sessions keep progressing
+// whether or not `accept` is being called.
+async fn drive(shared: Arc<Shared>, accepted: mpsc::Sender<UcpSession>, shutdown: Arc<Notify>) {
+    let (drop_sender, mut drop_receiver) = mpsc::channel(shared.config.listener_channel_capacity);
+    // No datagram of a session exceeds `max_mtu_size`; a longer OpenConnectionRequest1
+    // is cut short, which only lowers the MTU it claims.
+    let mut v = vec![0u8; shared.config.max_mtu_size as usize];
+    loop {
+        let (size, src) = tokio::select! {
+            rs = shared.socket.recv_from(&mut v) => match rs {
+                Ok(rs) => rs,
+                Err(_) => continue,
+            },
+            Some((addr, session)) = drop_receiver.recv() => {
+                shared.conns.lock().unwrap().remove(addr, &session);
+                continue;
+            }
+            _ = shutdown.notified() => break,
+        };
+        shared
+            .handle(&v[..size], src, &drop_sender, &accepted)
+            .await
+            .ok();
+    }
+    // Nothing reads the socket any more, so end the sessions left behind.
+    let conns = shared.conns.lock().unwrap().sessions();
+    for conn in conns {
+        conn.lock().await.shutdown().await.ok();
+    }
+}
+
+async fn into_session(mut session: UcpSession, shared: &Shared) -> Result<UcpSession> {
+    loop {
+        let got = session.recv().await?;
+        match got[0] {
+            ConnectionRequest::ID => {
+                let request: ConnectionRequest = decode_packet(&got)?;
+                match shared
+                    .admission
+                    .connection_request(session.peer_addr(), request.guid)
+                {
+                    Decision::Accept => {}
+                    Decision::Reject => {
+                        let reply = ConnectionRequestFailed {
+                            magic: (),
+                            server_guid: shared.guid,
+                        };
+                        session
+                            .send_syspacket(reply, Reliability::ReliableOrdered)
+                            .await?;
+                        session.close().await?;
+                        return Err(UcpError::ConnectionRequestFailed);
+                    }
+                    Decision::Ban => {
+                        let reply = ConnectionBanned {
+                            magic: (),
+                            server_guid: shared.guid,
+                        };
+                        session
+                            .send_syspacket(reply, Reliability::ReliableOrdered)
+                            .await?;
+                        session.close().await?;
+                        return Err(UcpError::ConnectionBanned);
+                    }
+                }
+                let accept = ConnectionRequestAccepted {
+                    client_address: canonical_addr(session.peer_addr()),
+                    system_index: 0,
+                    request_timestamp: request.time,
+                    accepted_timestamp: time(),
+                };
+                session
+                    .send_syspacket(accept, Reliability::ReliableOrdered)
+                    .await?;
+            }
+            // The ID alone completes the handshake; the address is only informative.
+            NewIncomingConnections::ID => {
+                session.observed_addr = decode_packet::<NewIncomingConnections>(&got)
+                    .ok()
+                    .map(|new_incoming| new_incoming.server_address);
+                return Ok(session);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Shared {
+    async fn handle(
+        self: &Arc<Self>,
+        v: &[u8],
+        src: SocketAddr,
+        drop_sender: &mpsc::Sender<(SocketAddr, Session)>,
+        accepted: &mpsc::Sender<UcpSession>,
+    ) -> std::io::Result<()> {
+        let mut reader = std::io::Cursor::new(v);
+        let id = u8::decode(&mut reader)?;
+        // Connected datagrams never start with an offline packet ID: plain ones have the
+        // high bit set and sealed ones start with a counter far below 2^56.
+        let offline = matches!(
+            id,
+            UnconnectedPing::ID
+                | UnconnectedPingOpenConnections::ID
+                | OpenConnectionRequest1::ID
+                | OpenConnectionRequest2::ID
+        );
+        let conn = self.conns.lock().unwrap().get(src);
+        if let Some(conn) = conn.filter(|_| !offline) {
+            let mut conn = conn.lock().await;
+            let result = conn.handle(v).await;
+            // The datagram may have answered a path challenge.
+            self.follow(src, conn.peer_addr());
+            return result;
+        }
+        if !offline && self.config.connection_migration {
+            return self.migrate(v, src).await;
+        }
+
+        match id {
+            UnconnectedPing::ID | UnconnectedPingOpenConnections::ID => {
+                self.handle_ping(v, src).await?
+            }
+            OpenConnectionRequest1::ID => self.handle_ocrequest1(v, src).await?,
+            OpenConnectionRequest2::ID => {
+                if let Some((conn, r, endpoints)) = self.handle_ocrequest2(v, src).await? {
+                    let session = UcpSession::init_with_conn(
+                        conn,
+                        r,
+                        endpoints,
+                        Some(drop_sender.clone()),
+                        None,
+                        &self.config,
+                    );
+                    let shared = self.clone();
+                    let accepted = accepted.clone();
+                    tokio::spawn(async move {
+                        // Refused, stalled and malformed handshakes are the peer's
+                        // business; dropping the session lets the listener forget it.
+                        let session =
+                            timeout(shared.config.accept_timeout, into_session(session, &shared))
+                                .await;
+                        if let Ok(Ok(session)) = session {
+                            accepted.send(session).await.ok();
+                        }
+                    });
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Looks for the session a connected datagram from an unknown port belongs to. It
+    // only moves there once it answers the path challenge this sends.
+    async fn migrate(&self, v: &[u8], src: SocketAddr) -> std::io::Result<()> {
+        let candidates = self.conns.lock().unwrap().same_ip(src);
+        for (addr, session) in candidates {
+            let mut conn = session.lock().await;
+            if conn.handle_migrated(v, src).await? {
+                self.follow(addr, conn.peer_addr());
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Files the session last seen at `from` under its current address.
+    fn follow(&self, from: SocketAddr, to: SocketAddr) {
+        if from != to {
+            self.conns.lock().unwrap().rebind(from, to);
+        }
+    }
+
+    fn connection_count(&self) -> usize {
+        self.conns.lock().unwrap().live_count()
+    }
+
+    // Clears the way for a client at `src` with `guid`, according to the duplicate
+    // policy. Returns false if the client has to be refused.
+    async fn resolve_duplicates(&self, src: SocketAddr, guid: u64) -> std::io::Result<bool> {
+        // An ended session the application still holds may be resending its goodbye,
+        // which must not reach the new client.
+        let ended = self.conns.lock().unwrap().take_ended(src);
+        if let Some(session) = ended {
+            session.lock().await.replace();
+        }
+        let duplicates = self.conns.lock().unwrap().duplicates(src, guid);
+        if duplicates.is_empty() {
+            return Ok(true);
+        }
+        if self.config.duplicate_policy == DuplicatePolicy::RejectNew {
+            let reply = AlreadyConnected {
+                magic: (),
+                server_guid: self.guid,
+            };
+            self.send_offline(reply, src).await?;
+            return Ok(false);
+        }
+        for (addr, session) in duplicates {
+            self.conns.lock().unwrap().remove(addr, &session);
+            let mut conn = session.lock().await;
+            if addr == src {
+                conn.replace();
+            } else {
+                conn.kick().await?;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn refuse(&self, decision: Decision, src: SocketAddr) -> std::io::Result<()> {
+        match decision {
+            Decision::Accept => Ok(()),
+            Decision::Reject => {
+                let reply = ConnectionRequestFailed {
+                    magic: (),
+                    server_guid: self.guid,
+                };
+                self.send_offline(reply, src).await
+            }
+            Decision::Ban => {
+                let reply = ConnectionBanned {
+                    magic: (),
+                    server_guid: self.guid,
+                };
+                self.send_offline(reply, src).await
+            }
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.config
+            .max_connections
+            .map(|max| self.connection_count() >= max)
+            .unwrap_or(false)
+    }
+
+    async fn send_offline<P: SystemPacket>(
+        &self,
+        packet: P,
+        dst: SocketAddr,
+    ) -> std::io::Result<()> {
+        let mut bytes = vec![];
+        encode_syspacket(packet, &mut bytes)?;
+        self.socket.send_to(&bytes[..], dst).await?;
+        Ok(())
+    }
+
+    async fn reject_full(&self, src: SocketAddr) -> std::io::Result<()> {
+        let reply = NoFreeIncomingConnections {
+            magic: (),
+            server_guid: self.guid,
+        };
+        self.send_offline(reply, src).await
+    }
+
+    async fn handle_ping(&self, v: &[u8], src: SocketAddr) -> std::io::Result<()> {
+        let time_stamp = if v[0] == UnconnectedPing::ID {
+            decode_syspacket::<UnconnectedPing>(v)?.time_stamp
+        } else {
+            // Only servers with free slots answer this variant.
+            if self.is_full() {
+                return Ok(());
+            }
+            decode_syspacket::<UnconnectedPingOpenConnections>(v)?.time_stamp
+        };
+        let provider = self.motd_provider.read().unwrap().clone();
+        let motd = match provider {
+            Some(provider) => provider.motd(canonical_addr(src), self.connection_count()),
+            None => self.title.read().unwrap().clone(),
+        };
+        let pong = UnconnectedPong {
+            time: time_stamp,
+            guid: self.guid,
+            magic: (),
+            motd,
+        };
+        self.send_offline(pong, src).await
+    }
+
+    async fn handle_ocrequest1(&self, v: &[u8], src: SocketAddr) -> std::io::Result<()> {
+        let packet: OpenConnectionRequest1 = decode_syspacket(v)?;
+        // Too small to carry a frame; no real client starts below the base MTU.
+        if packet.mtu_size < BASE_MTU {
+            return Ok(());
+        }
+
+        if packet.protocol_version != PROTOCOL_VERSION {
+            let reply = IncompatibleProtocolVersion {
+                server_protocol: PROTOCOL_VERSION,
+                magic: (),
+                server_guid: self.guid,
+            };
+            return self.send_offline(reply, src).await;
+        }
+        let decision = self.admission.open_connection(src, None, packet.mtu_size);
+        if decision != Decision::Accept {
+            return self.refuse(decision, src).await;
+        }
+        if self.is_full() {
+            return self.reject_full(src).await;
+        }
+        let reply = OpenConnectionReply1 {
+            magic: (),
+            guid: self.guid,
+            cookie: self.cookies.as_ref().map(|jar| jar.issue(src)),
+            server_key: self.config.server_key.as_ref().map(ServerKey::public_key),
+            mtu_size: cmp::min(packet.mtu_size, self.config.max_mtu_size),
+        };
+        self.send_offline(reply, src).await
+    }
+
+    async fn handle_ocrequest2(
+        &self,
+        v: &[u8],
+        src: SocketAddr,
+    ) -> std::io::Result<Option<(Session, PacketReceiver, Endpoints)>> {
+        let packet: OpenConnectionRequest2 = decode_syspacket(v)?;
+        if packet.mtu < BASE_MTU {
+            return Ok(None);
+        }
+        if let Some(jar) = &self.cookies {
+            // Unverified sources get nothing, not even a rejection.
+            if !packet.cookie.is_some_and(|cookie| jar.verify(src, cookie)) {
+                return Ok(None);
+            }
+        }
+        // A repeated request, the reply to the first one was probably lost.
+        let pending = self.conns.lock().unwrap().pending(src, packet.guid);
+        if let Some((session, reply)) = pending {
+            if !session.lock().await.connection_requested() {
+                self.socket.send_to(&reply, src).await?;
+                return Ok(None);
+            }
+        }
+        let decision = self
+            .admission
+            .open_connection(src, Some(packet.guid), packet.mtu);
+        if decision != Decision::Accept {
+            self.refuse(decision, src).await?;
+            return Ok(None);
+        }
+        // Settled before duplicates are resolved, so a refused client kicks nobody.
+        let keys = match (&self.config.server_key, packet.client_key) {
+            (Some(server_key), Some(client_key)) => {
+                let ephemeral = EphemeralKey::generate();
+                match ephemeral.server_keys(server_key, client_key) {
+                    Some(keys) => Some((ephemeral.public, keys)),
+                    None => {
+                        self.refuse(Decision::Reject, src).await?;
+                        return Ok(None);
+                    }
+                }
+            }
+            (Some(_), None) if self.config.require_encryption => {
+                self.refuse(Decision::Reject, src).await?;
+                return Ok(None);
+            }
+            _ => None,
+        };
+        if !self.resolve_duplicates(src, packet.guid).await? {
+            return Ok(None);
+        }
+        if self.is_full() {
+            self.reject_full(src).await?;
+            return Ok(None);
+        }
+        let mtu = cmp::min(packet.mtu, self.config.max_mtu_size);
+        let reply = OpenConnectionReply2 {
+            magic: (),
+            guid: self.guid,
+            address: canonical_addr(src),
+            mtu,
+            server_key: keys.as_ref().map(|(public, _)| *public),
+        };
+        let mut reply_bytes = vec![];
+        encode_syspacket(reply, &mut reply_bytes)?;
+        self.socket.send_to(&reply_bytes, src).await?;
+        let endpoints = Endpoints {
+            peer_addr: PeerAddr::new(src),
+            peer_guid: packet.guid,
+            local_addr: self.socket.local_addr()?,
+        };
+        let (s, r) = packet_channel(self.config.session_channel_capacity);
+        let reason = r.reason_cell();
+        let session = Arc::new(Mutex::new(Conn::new(
+            endpoints.peer_addr.clone(),
+            mtu as usize,
+            self.socket.clone(),
+            s,
+            &self.config,
+            keys.map(|(_, keys)| keys),
+        )));
+        self.conns.lock().unwrap().insert(
+            src,
+            ConnEntry {
+                session: session.clone(),
+                guid: packet.guid,
+                reply: reply_bytes,
+                reason,
+            },
+        );
+        Ok(Some((session, r, endpoints)))
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+/// Builds the UnconnectedPong payload for every ping a listener answers.
+pub trait MotdProvider: Send + Sync {
+    /// `connections` is the number of sessions the listener currently holds.
+    fn motd(&self, requester: SocketAddr, connections: usize) -> String;
+}
+
+impl<F> MotdProvider for F
+where
+    F: Fn(SocketAddr, usize) -> String + Send + Sync,
+{
+    fn motd(&self, requester: SocketAddr, connections: usize) -> String {
+        self(requester, connections)
+    }
+}
+
+/// The `;` separated MOTD Minecraft: Bedrock Edition servers put in UnconnectedPong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BedrockMotd {
+    pub edition: String,
+    pub motd: String,
+    pub protocol: u32,
+    pub version: String,
+    pub online_players: u32,
+    pub max_players: u32,
+    pub server_id: Option<u64>,
+    pub level_name: Option<String>,
+    pub game_mode: Option<String>,
+    pub game_mode_id: Option<u8>,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+}
+
+#[derive(Debug)]
+pub struct ParseMotdError;
+
+impl fmt::Display for ParseMotdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid bedrock motd")
+    }
+}
+
+impl std::error::Error for ParseMotdError {}
+
+impl fmt::Display for BedrockMotd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: ToString>(v: &Option<T>) -> String {
+            v.as_ref().map(|v| v.to_string()).unwrap_or_default()
+        }
+        let mut fields = vec![
+            self.edition.clone(),
+            self.motd.clone(),
+            self.protocol.to_string(),
+            self.version.clone(),
+            self.online_players.to_string(),
+            self.max_players.to_string(),
+            opt(&self.server_id),
+            opt(&self.level_name),
+            opt(&self.game_mode),
+            opt(&self.game_mode_id),
+            opt(&self.port_v4),
+            opt(&self.port_v6),
+        ];
+        // Older servers stop after the player counts, so unset trailing fields are left out.
+        while fields.len() > 6 && fields.last().map(|f| f.is_empty()).unwrap_or(false) {
+            fields.pop();
+        }
+        write!(f, "{};", fields.join(";"))
+    }
+}
+
+impl FromStr for BedrockMotd {
+    type Err = ParseMotdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(';');
+        let mut required = || fields.next().ok_or(ParseMotdError);
+        let edition = required()?.to_owned();
+        let motd = required()?.to_owned();
+        let protocol = required()?.parse().map_err(|_| ParseMotdError)?;
+        let version = required()?.to_owned();
+        let online_players = required()?.parse().map_err(|_| ParseMotdError)?;
+        let max_players = required()?.parse().map_err(|_| ParseMotdError)?;
+
+        let mut optional = || fields.next().filter(|f| !f.is_empty());
+        Ok(Self {
+            edition,
+            motd,
+            protocol,
+            version,
+            online_players,
+            max_players,
+            server_id: optional().and_then(|f| f.parse().ok()),
+            level_name: optional().map(str::to_owned),
+            game_mode: optional().map(str::to_owned),
+            game_mode_id: optional().and_then(|f| f.parse().ok()),
+            port_v4: optional().and_then(|f| f.parse().ok()),
+            port_v6: optional().and_then(|f| f.parse().ok()),
+        })
+    }
+}
+
+impl From<BedrockMotd> for String {
+    fn from(motd: BedrockMotd) -> Self {
+        motd.to_string()
+    }
+}
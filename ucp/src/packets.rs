@@ -88,7 +88,7 @@ impl Frame {
             flag |= FRAGMENT_FLAG
         }
         u8::encode(&flag, writer)?;
-        Big::encode(&((self.length * 8) as u16), writer)?;
+        Big::encode(&(self.length * 8), writer)?;
         if self.reliability.reliable() {
             U24::encode(&self.mindex, writer)?;
         }
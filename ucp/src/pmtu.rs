@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+// Sizes here include the 32 bytes `UDP_HEADER`, like the MTU negotiated by the handshake.
+
+// Fallback after a black hole; the smallest datagram every IPv4 path must carry.
+pub(crate) const BASE_MTU: u16 = 576;
+// The search stops once the remaining range is narrower than this.
+const SEARCH_STEP: u16 = 16;
+// Lost probes of one size before that size is considered too large.
+const PROBE_ATTEMPTS: u32 = 2;
+// How long a finished search is trusted before probing upwards again.
+const RAISE_INTERVAL: Duration = Duration::from_secs(600);
+
+struct Probe {
+    sequence: u32,
+    size: u16,
+    sent: Instant,
+}
+
+// Datagram packetization layer PMTU discovery (RFC 8899), using padded
+// DetectLostConnections frames as probes. The MTU negotiated by the handshake is the
+// upper bound, as the peer may not take anything larger; probing only climbs back
+// towards it after a black hole.
+pub(crate) struct PathMtu {
+    pub mtu: u16,
+    // Largest size that may still work.
+    ceiling: u16,
+    max: u16,
+    enabled: bool,
+    probe: Option<Probe>,
+    failures: u32,
+    next_search: Option<Instant>,
+}
+
+impl PathMtu {
+    pub fn new(mtu: u16, enabled: bool) -> Self {
+        Self {
+            mtu,
+            ceiling: mtu,
+            max: mtu,
+            enabled,
+            probe: None,
+            failures: 0,
+            next_search: None,
+        }
+    }
+
+    // The size of the next probe to send, if one is due.
+    pub fn next_probe(&mut self, now: Instant) -> Option<u16> {
+        if !self.enabled || self.probe.is_some() {
+            return None;
+        }
+        if let Some(next_search) = self.next_search {
+            if now < next_search {
+                return None;
+            }
+            self.next_search = None;
+            self.ceiling = self.max;
+        }
+        if self.ceiling < self.mtu.saturating_add(SEARCH_STEP) {
+            self.next_search = Some(now + RAISE_INTERVAL);
+            return None;
+        }
+        Some(self.mtu + (self.ceiling - self.mtu).div_ceil(2))
+    }
+
+    pub fn probe_sent(&mut self, sequence: u32, size: u16, now: Instant) {
+        self.probe = Some(Probe {
+            sequence,
+            size,
+            sent: now,
+        });
+    }
+
+    // Returns the new MTU when `sequence` acknowledges the outstanding probe.
+    pub fn on_ack(&mut self, sequence: u32) -> Option<u16> {
+        let probe = self.probe.take_if(|probe| probe.sequence == sequence)?;
+        self.failures = 0;
+        (probe.size > self.mtu).then(|| {
+            self.mtu = probe.size;
+            self.mtu
+        })
+    }
+
+    pub fn check_timeout(&mut self, now: Instant, rto: Duration) {
+        let Some(probe) = self
+            .probe
+            .take_if(|probe| now.duration_since(probe.sent) > rto)
+        else {
+            return;
+        };
+        self.failures += 1;
+        if self.failures >= PROBE_ATTEMPTS {
+            self.failures = 0;
+            self.ceiling = probe.size - 1;
+        }
+    }
+
+    pub fn can_fall_back(&self) -> bool {
+        self.enabled && self.mtu > BASE_MTU
+    }
+
+    // Large datagrams keep getting lost: fall back to the base MTU and search again.
+    // Returns the new MTU, if it changed.
+    pub fn on_black_hole(&mut self) -> Option<u16> {
+        if !self.can_fall_back() {
+            return None;
+        }
+        self.ceiling = self.mtu - 1;
+        self.mtu = BASE_MTU;
+        self.probe = None;
+        self.failures = 0;
+        self.next_search = None;
+        Some(self.mtu)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::HashSet,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    net::UdpSocket,
+    sync::mpsc,
+    time::{sleep_until, timeout_at},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use crate::{
+    bind_socket, canonical_addr,
+    motd::{BedrockMotd, ParseMotdError},
+    peer_addr_for,
+    system_packets::{decode_packet, encode_syspacket, UnconnectedPing, UnconnectedPong},
+    Result, UcpError,
+};
+
+const PING_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug)]
+pub struct PongInfo {
+    pub guid: u64,
+    pub motd: String,
+    pub latency: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoveredServer {
+    pub addr: SocketAddr,
+    pub guid: u64,
+    pub motd: String,
+    pub latency: Duration,
+}
+
+impl PongInfo {
+    pub fn bedrock_motd(&self) -> std::result::Result<BedrockMotd, ParseMotdError> {
+        self.motd.parse()
+    }
+}
+
+impl DiscoveredServer {
+    pub fn bedrock_motd(&self) -> std::result::Result<BedrockMotd, ParseMotdError> {
+        self.motd.parse()
+    }
+}
+
+// The timestamp is echoed back in the pong, so every retry can be timed on its own.
+fn encode_ping(start: Instant) -> std::io::Result<Vec<u8>> {
+    let ping = UnconnectedPing {
+        time_stamp: start.elapsed().as_micros() as u64,
+        magic: (),
+        guid: 0,
+    };
+    let mut bytes = vec![];
+    encode_syspacket(ping, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn latency(start: Instant, pong: &UnconnectedPong) -> Duration {
+    start
+        .elapsed()
+        .saturating_sub(Duration::from_micros(pong.time))
+}
+
+/// Sends UnconnectedPing to `remote` until an UnconnectedPong arrives or `timeout` passes.
+pub async fn ping(remote: SocketAddr, timeout: Duration) -> Result<PongInfo> {
+    let local = match remote {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let udp = bind_socket(local).await?;
+    let remote = peer_addr_for(udp.local_addr()?, remote);
+
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::from_std(start + timeout);
+    let pong = async {
+        loop {
+            udp.send_to(&encode_ping(start)?, remote).await?;
+
+            let retry = tokio::time::Instant::now() + PING_RETRY_INTERVAL;
+            let recv = async {
+                loop {
+                    let mut v = [0u8; 2048];
+                    let (size, src) = udp.recv_from(&mut v).await?;
+                    if src != remote {
+                        continue;
+                    }
+                    if let Ok(pong) = decode_packet::<UnconnectedPong>(&v[..size]) {
+                        return Ok::<_, UcpError>(pong);
+                    }
+                }
+            };
+            tokio::select! {
+                r = recv => return r,
+                _ = sleep_until(retry) => {}
+            }
+        }
+    };
+    let pong = timeout_at(deadline, pong)
+        .await
+        .map_err(|_| UcpError::Timeout)??;
+
+    Ok(PongInfo {
+        guid: pong.guid,
+        latency: latency(start, &pong),
+        motd: pong.motd,
+    })
+}
+
+/// Broadcasts UnconnectedPing to `port` on every local network for `duration`.
+/// Each server is yielded once, the first time its pong arrives.
+pub async fn discover(
+    port: u16,
+    duration: Duration,
+) -> Result<impl Stream<Item = DiscoveredServer>> {
+    let mut targets = vec![SocketAddr::new(Ipv4Addr::BROADCAST.into(), port)];
+    for interface in if_addrs::get_if_addrs()? {
+        if interface.is_loopback() {
+            continue;
+        }
+        let target = match &interface.addr {
+            if_addrs::IfAddr::V4(addr) => match addr.broadcast {
+                Some(broadcast) => SocketAddr::new(broadcast.into(), port),
+                None => continue,
+            },
+            if_addrs::IfAddr::V6(_) => match interface.index {
+                // All-nodes link-local multicast group.
+                Some(index) => SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1),
+                    port,
+                    0,
+                    index,
+                )),
+                None => continue,
+            },
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    discover_at(targets, duration).await
+}
+
+/// Like [`discover`], but pings `targets` instead of the local broadcast addresses.
+/// Targets may be unicast, broadcast or multicast addresses.
+pub async fn discover_at(
+    targets: impl IntoIterator<Item = SocketAddr>,
+    duration: Duration,
+) -> Result<impl Stream<Item = DiscoveredServer>> {
+    let v4 = bind_socket(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)).await?;
+    v4.set_broadcast(true)?;
+    // IPv6 is optional, hosts without it still discover IPv4 servers.
+    let v6 = bind_socket(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0))
+        .await
+        .ok();
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|target| target.is_ipv4() || v6.is_some())
+        .collect();
+
+    let (s, r) = mpsc::channel(32);
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let deadline = tokio::time::Instant::from_std(start + duration);
+        let mut seen = HashSet::new();
+        let found = async {
+            loop {
+                if let Ok(bytes) = encode_ping(start) {
+                    for target in &targets {
+                        let udp = match (target, &v6) {
+                            (SocketAddr::V6(_), Some(v6)) => v6,
+                            _ => &v4,
+                        };
+                        udp.send_to(&bytes, target).await.ok();
+                    }
+                }
+
+                let retry = tokio::time::Instant::now() + PING_RETRY_INTERVAL;
+                let recv = async {
+                    loop {
+                        let (pong, src) = tokio::select! {
+                            r = recv_pong(&v4) => r,
+                            r = recv_pong_opt(v6.as_ref()) => r,
+                        };
+                        if !seen.insert(pong.guid) {
+                            continue;
+                        }
+                        let server = DiscoveredServer {
+                            addr: canonical_addr(src),
+                            guid: pong.guid,
+                            latency: latency(start, &pong),
+                            motd: pong.motd,
+                        };
+                        if s.send(server).await.is_err() {
+                            return;
+                        }
+                    }
+                };
+                tokio::select! {
+                    _ = recv => return,
+                    _ = sleep_until(retry) => {}
+                }
+            }
+        };
+        timeout_at(deadline, found).await.ok();
+    });
+    Ok(ReceiverStream::new(r))
+}
+
+async fn recv_pong(udp: &UdpSocket) -> (UnconnectedPong, SocketAddr) {
+    loop {
+        let mut v = [0u8; 2048];
+        if let Ok((size, src)) = udp.recv_from(&mut v).await {
+            if let Ok(pong) = decode_packet::<UnconnectedPong>(&v[..size]) {
+                return (pong, src);
+            }
+        }
+    }
+}
+
+async fn recv_pong_opt(udp: Option<&UdpSocket>) -> (UnconnectedPong, SocketAddr) {
+    match udp {
+        Some(udp) => recv_pong(udp).await,
+        None => std::future::pending().await,
+    }
+}
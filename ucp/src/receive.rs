@@ -67,7 +67,10 @@ impl ReceiveQueue {
     }
 
     pub fn fragmented(&mut self, frame: Frame, bytes: &[u8]) -> Option<Vec<u8>> {
-        if let Some(fragment) = frame.fragment {
+        if let Some(fragment) = frame
+            .fragment
+            .filter(|fragment| fragment.index < fragment.size)
+        {
             if let Entry::Vacant(e) = self.fragment.entry(fragment.id) {
                 let mut bmap = BTreeMap::new();
                 bmap.insert(fragment.index, bytes.to_vec());
@@ -76,10 +79,9 @@ impl ReceiveQueue {
                 let mng = self.fragment.get_mut(&fragment.id).unwrap();
                 mng.1.insert(fragment.index, bytes.to_vec());
                 if mng.0 as usize == mng.1.len() {
-                    let mut ret = vec![];
-                    for i in 0..mng.0 {
-                        ret.append(&mut mng.1.remove(&i).unwrap());
-                    }
+                    // Ids wrap around, so a finished split must not linger.
+                    let (_, fragments) = self.fragment.remove(&fragment.id).unwrap();
+                    let ret = fragments.into_values().flatten().collect();
                     if !frame.reliability.sequenced() && !frame.reliability.ordered() {
                         return Some(ret);
                     } else {
@@ -104,6 +106,17 @@ impl ReceiveQueue {
         }
     }
 
+    pub fn pending_fragments(&self) -> usize {
+        self.fragment
+            .values()
+            .map(|(_, fragments)| fragments.len())
+            .sum()
+    }
+
+    pub fn out_of_order(&self) -> usize {
+        self.ordered.len()
+    }
+
     pub fn next_ordered(&mut self) -> Option<Vec<u8>> {
         let first = *self.ordered.iter().next()?.0;
         if first == self.ordered_next {
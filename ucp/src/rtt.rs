@@ -0,0 +1,48 @@
+use std::{cmp, collections::VecDeque, time::Duration};
+
+// Number of recent samples the statistics are computed over.
+const WINDOW: usize = 32;
+
+/// Round-trip times over the most recent samples, taken from both acks and pings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RttStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Mean difference between consecutive samples.
+    pub jitter: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct RttMeter {
+    samples: VecDeque<Duration>,
+}
+
+impl RttMeter {
+    pub fn sample(&mut self, rtt: Duration) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(rtt);
+    }
+
+    pub fn stats(&self) -> Option<RttStats> {
+        let count = self.samples.len() as u32;
+        let min = *self.samples.iter().min()?;
+        let max = *self.samples.iter().max()?;
+        let avg = self.samples.iter().sum::<Duration>() / count;
+        let jitter = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(a, b)| a.abs_diff(*b))
+            .sum::<Duration>()
+            / cmp::max(count - 1, 1);
+        Some(RttStats {
+            min,
+            avg,
+            max,
+            jitter,
+        })
+    }
+}
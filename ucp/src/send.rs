@@ -1,13 +1,17 @@
 use crate::{
+    config::UcpConfig,
+    crypto::{Sealer, SEAL_OVERHEAD},
     cubic::Cubic,
     packets::{FragmentHeader, Frame, Reliability},
-    system_packets::{Ack, Nack},
+    pmtu::{PathMtu, BASE_MTU},
+    stats::ConnectionStats,
+    system_packets::{encode_syspacket, Ack, DetectLostConnections, Nack},
     Udp,
 };
 use packet_derive::*;
 use std::{
     cmp,
-    collections::VecDeque,
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     net::SocketAddr,
     time::{Duration, Instant},
 };
@@ -15,8 +19,10 @@ use std::{
 const UDP_HEADER: usize = 32;
 const DATAGRAM_FLAG: u8 = 0x80;
 const NEEDS_B_AND_AS_FLAG: u8 = 0x4;
-const MAX_RTO: Duration = Duration::from_secs(10);
-const MIN_RTO: Duration = Duration::from_millis(1000);
+// Resends of a datagram larger than the base MTU before a black hole is suspected. It
+// is only assumed once a small datagram sent since then got through while large ones
+// keep getting lost, which congestion alone does not explain.
+const BLACK_HOLE_RESENDS: u32 = 2;
 
 #[derive(Clone)]
 pub(crate) struct OutPacket {
@@ -37,6 +43,18 @@ impl OutPacket {
     }
 }
 
+// Payload of a fragmented packet, kept until every fragment is acked so it can be
+// split again when the MTU shrinks.
+struct Split {
+    data: Vec<u8>,
+    reliability: Reliability,
+    sindex: u32,
+    oindex: u32,
+    queued: usize,
+    // Once the peer holds some fragments, the split has to be completed under its id.
+    acked: usize,
+}
+
 struct SentConf {
     pub sequence: u32,
     pub time: Instant,
@@ -54,13 +72,17 @@ struct Rtts {
 struct Rto {
     pub rto: Duration,
     rtts: Option<Rtts>,
+    min: Duration,
+    max: Duration,
 }
 
 impl Rto {
-    pub fn new() -> Self {
+    pub fn new(min: Duration, max: Duration) -> Self {
         Self {
-            rto: Duration::from_secs(1),
+            rto: cmp::max(cmp::min(Duration::from_secs(1), max), min),
             rtts: None,
+            min,
+            max,
         }
     }
 
@@ -70,7 +92,7 @@ impl Rto {
                 rtts.rttvar.mul_f32(1. - BETA) + absolute_div(rtts.srtt, rtt).mul_f32(BETA);
             let new_srtt = rtts.srtt.mul_f32(1. - ALPHA) + rtt.mul_f32(ALPHA);
             let mut rto = rtts.srtt + K * rtts.rttvar;
-            rto = cmp::max(cmp::min(rto, MAX_RTO), MIN_RTO);
+            rto = cmp::max(cmp::min(rto, self.max), self.min);
             self.rto = rto;
             rtts.srtt = new_srtt;
             rtts.rttvar = new_rttvar;
@@ -78,7 +100,7 @@ impl Rto {
             let srtt = rtt;
             let rttvar = rtt / 2;
             let rto = srtt + K * rttvar;
-            self.rto = cmp::max(cmp::min(rto, MAX_RTO), MIN_RTO);
+            self.rto = cmp::max(cmp::min(rto, self.max), self.min);
             self.rtts = Some(Rtts { srtt, rttvar });
         }
     }
@@ -95,6 +117,7 @@ pub(crate) struct DatagramSender {
     cubic: Cubic,
 
     rto: Rto,
+    max_resends: u32,
 
     sequence: u32,
 
@@ -105,30 +128,79 @@ pub(crate) struct DatagramSender {
     oindex: u32,
 
     fragment_id: u16,
+    splits: HashMap<u16, Split>,
 
     nodelay: bool,
+
+    sealer: Option<Sealer>,
+    overhead: usize,
+
+    pmtu: PathMtu,
+    // Since when large datagrams are suspected to vanish in a black hole.
+    suspected: Option<Instant>,
+    // When the latest acked datagram no larger than the base MTU was sent.
+    small_acked: Option<Instant>,
+    // A lone DetectLostConnections sent to find out whether small datagrams arrive.
+    small_probe: Option<SentConf>,
+
+    stats: ConnectionStats,
 }
 
 impl DatagramSender {
-    pub fn new(udp: Udp, address: SocketAddr, mtu: usize) -> Self {
+    pub fn new(
+        udp: Udp,
+        address: SocketAddr,
+        mtu: usize,
+        config: &UcpConfig,
+        sealer: Option<Sealer>,
+    ) -> Self {
+        let overhead = sealer.as_ref().map(|_| SEAL_OVERHEAD).unwrap_or(0);
         Self {
             udp,
             address,
-            max_payload_len: mtu - UDP_HEADER - 4,
+            max_payload_len: mtu - UDP_HEADER - 4 - overhead,
             buffer: VecDeque::new(),
             sent: vec![],
             cubic: Cubic::new(mtu),
-            rto: Rto::new(),
+            rto: Rto::new(config.min_rto, config.max_rto),
+            max_resends: config.max_resends,
             sequence: 0,
             is_congestion: false,
             mindex: 0,
             sindex: 0,
             oindex: 0,
             fragment_id: 0,
+            splits: HashMap::new(),
             nodelay: false,
+            sealer,
+            overhead,
+            pmtu: PathMtu::new(mtu as u16, config.mtu_discovery),
+            suspected: None,
+            small_acked: None,
+            small_probe: None,
+            stats: ConnectionStats::default(),
         }
     }
 
+    fn payload_len(&self, mtu: u16) -> usize {
+        mtu as usize - UDP_HEADER - 4 - self.overhead
+    }
+
+    pub async fn send_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.send_raw_to(bytes, self.address).await
+    }
+
+    // Every datagram of the session, acks included, leaves through here.
+    async fn send_raw_to(&mut self, bytes: &[u8], address: SocketAddr) -> std::io::Result<()> {
+        let len = match &mut self.sealer {
+            Some(sealer) => self.udp.send_to(&sealer.seal(bytes), address).await?,
+            None => self.udp.send_to(bytes, address).await?,
+        };
+        self.stats.bytes_sent += len as u64;
+        self.stats.datagrams_sent += 1;
+        Ok(())
+    }
+
     fn sendable_packet_index(&self) -> Option<usize> {
         self.buffer.iter().position(|stack| stack.1.is_none())
     }
@@ -146,17 +218,9 @@ impl DatagramSender {
                 }
             }
 
-            let mut buff = vec![];
-            let mut writer = std::io::Cursor::new(&mut buff);
-            let id = DATAGRAM_FLAG | NEEDS_B_AND_AS_FLAG;
-
-            u8::encode(&id, &mut writer)?;
-            U24::encode(&self.sequence, &mut writer)?;
-
-            for out in self.buffer[next_packet].0.iter() {
-                buff.append(&mut out.encode()?);
-            }
-            self.udp.send_to(&buff, self.address).await?;
+            let buff = self.datagram(&self.buffer[next_packet].0)?;
+            self.send_raw(&buff).await?;
+            self.stats.frames_sent += self.buffer[next_packet].0.len() as u64;
 
             self.buffer[next_packet].1 = Some(SentConf {
                 sequence: self.sequence,
@@ -170,6 +234,20 @@ impl DatagramSender {
         Ok(())
     }
 
+    fn datagram(&self, outs: &[OutPacket]) -> std::io::Result<Vec<u8>> {
+        let mut buff = vec![];
+        let mut writer = std::io::Cursor::new(&mut buff);
+        let id = DATAGRAM_FLAG | NEEDS_B_AND_AS_FLAG;
+
+        u8::encode(&id, &mut writer)?;
+        U24::encode(&self.sequence, &mut writer)?;
+
+        for out in outs {
+            buff.append(&mut out.encode()?);
+        }
+        Ok(buff)
+    }
+
     fn push_outpacket(&mut self, out: OutPacket) {
         if let Some((dst, None, None)) = self.buffer.back_mut() {
             let length = dst.iter().map(|out| out.length()).sum::<usize>();
@@ -223,43 +301,10 @@ impl DatagramSender {
                 Reliability::UnreliableSequenced => reliability = Reliability::ReliableSequenced,
                 _ => {}
             }
-            let header_size = Frame::size(reliability, true);
-            let payload_size = self.max_payload_len - header_size;
-            let m = bytes.len() % payload_size;
-            let mut count = (bytes.len() - m) / payload_size;
-            if m != 0 {
-                count += 1;
-            }
-
-            for i in 0..count {
-                let mut length = payload_size;
-                if i == count - 1 {
-                    length = m;
-                }
-
-                let header = FragmentHeader {
-                    size: count as u32,
-                    id: self.fragment_id,
-                    index: i as u32,
-                };
-                let frame = Frame {
-                    reliability,
-                    length: length as u16,
-                    mindex: self.mindex,
-                    sindex: self.sindex,
-                    oindex: self.oindex,
-                    fragment: Some(header),
-                };
-                self.mindex += 1;
-
-                let pos = i * payload_size;
-                self.push_outpacket(OutPacket {
-                    frame,
-                    data: bytes[pos..pos + length].to_vec(),
-                });
+            let count = self.split(bytes.to_vec(), reliability, self.sindex, self.oindex);
+            for _ in 0..count {
                 self.send_next().await?;
             }
-            self.fragment_id += 1;
             if reliability.ordered() {
                 self.oindex += 1;
             }
@@ -272,10 +317,172 @@ impl DatagramSender {
         Ok(())
     }
 
-    pub async fn ack(&mut self, ack: Ack) -> std::io::Result<()> {
+    // Queues `bytes` as the fragments of a new split packet and returns their count.
+    fn split(
+        &mut self,
+        bytes: Vec<u8>,
+        reliability: Reliability,
+        sindex: u32,
+        oindex: u32,
+    ) -> usize {
+        let header_size = Frame::size(reliability, true);
+        let payload_size = self.max_payload_len - header_size;
+        let count = bytes.len().div_ceil(payload_size);
+
+        for (i, chunk) in bytes.chunks(payload_size).enumerate() {
+            let header = FragmentHeader {
+                size: count as u32,
+                id: self.fragment_id,
+                index: i as u32,
+            };
+            let frame = Frame {
+                reliability,
+                length: chunk.len() as u16,
+                mindex: self.mindex,
+                sindex,
+                oindex,
+                fragment: Some(header),
+            };
+            self.mindex += 1;
+            self.push_outpacket(OutPacket {
+                frame,
+                data: chunk.to_vec(),
+            });
+        }
+        self.splits.insert(
+            self.fragment_id,
+            Split {
+                data: bytes,
+                reliability,
+                sindex,
+                oindex,
+                queued: count,
+                acked: 0,
+            },
+        );
+        self.fragment_id = self.fragment_id.wrapping_add(1);
+        count
+    }
+
+    fn release_fragments(&mut self, stack: &[OutPacket]) {
+        for header in stack.iter().filter_map(|out| out.frame.fragment.as_ref()) {
+            if let Entry::Occupied(mut split) = self.splits.entry(header.id) {
+                split.get_mut().queued -= 1;
+                split.get_mut().acked += 1;
+                if split.get().queued == 0 {
+                    split.remove();
+                }
+            }
+        }
+    }
+
+    // Applies a new MTU. Queued datagrams that no longer fit are broken up, and
+    // oversized fragments are replaced by splitting their whole packet again, unless
+    // the peer already holds part of it. Those are resent as they are, each in a
+    // datagram of its own, as the peer can only complete the split under its id.
+    fn resize(&mut self, mtu: u16) {
+        self.max_payload_len = self.payload_len(mtu);
+        let max = self.max_payload_len;
+        let resplit: HashSet<u16> = self
+            .buffer
+            .iter()
+            .flat_map(|(stack, _, _)| stack)
+            .filter(|out| out.length() > max)
+            .filter_map(|out| out.frame.fragment.as_ref().map(|header| header.id))
+            .filter(|id| self.splits.get(id).is_some_and(|split| split.acked == 0))
+            .collect();
+
+        for (stack, conf, count) in std::mem::take(&mut self.buffer) {
+            let (dropped, stack): (Vec<_>, Vec<_>) = stack.into_iter().partition(|out| {
+                out.frame
+                    .fragment
+                    .as_ref()
+                    .is_some_and(|header| resplit.contains(&header.id))
+            });
+            let length = stack.iter().map(OutPacket::length).sum::<usize>();
+            if !stack.is_empty() && length <= max {
+                self.buffer.push_back((stack, conf, count));
+            } else {
+                if let Some(conf) = &conf {
+                    self.sent.retain(|seq| *seq != conf.sequence);
+                }
+                for out in stack {
+                    if conf.is_some() && !out.frame.reliability.reliable() {
+                        continue;
+                    }
+                    if out.length() <= max || out.frame.fragment.is_some() {
+                        self.push_outpacket(out);
+                        continue;
+                    }
+                    let reliability = match out.frame.reliability {
+                        Reliability::Unreliable => Reliability::Reliable,
+                        Reliability::UnreliableSequenced => Reliability::ReliableSequenced,
+                        reliability => reliability,
+                    };
+                    self.split(out.data, reliability, out.frame.sindex, out.frame.oindex);
+                }
+            }
+            for header in dropped.iter().filter_map(|out| out.frame.fragment.as_ref()) {
+                if let Some(split) = self.splits.remove(&header.id) {
+                    self.split(split.data, split.reliability, split.sindex, split.oindex);
+                }
+            }
+        }
+    }
+
+    // A padded DetectLostConnections; acked means datagrams of `size` make it through.
+    // Peers answer it with the ack alone, so it cannot be taken for a ping.
+    async fn send_probe(&mut self, size: u16) -> std::io::Result<()> {
+        let mut probe = vec![];
+        encode_syspacket(DetectLostConnections {}, &mut probe)?;
+        let length = self.payload_len(size) - Frame::size(Reliability::Unreliable, false);
+        probe.resize(length, 0);
+        self.pmtu.probe_sent(self.sequence, size, Instant::now());
+        self.send_lone(probe, self.address).await
+    }
+
+    async fn send_small_probe(&mut self) -> std::io::Result<()> {
+        let mut probe = vec![];
+        encode_syspacket(DetectLostConnections {}, &mut probe)?;
+        self.small_probe = Some(SentConf {
+            sequence: self.sequence,
+            time: Instant::now(),
+        });
+        self.send_lone(probe, self.address).await
+    }
+
+    // Sends `bytes` as an unreliable frame in a datagram of its own to `address`,
+    // bypassing the send queue.
+    pub async fn send_lone(&mut self, bytes: Vec<u8>, address: SocketAddr) -> std::io::Result<()> {
+        let out = OutPacket {
+            frame: Frame {
+                reliability: Reliability::Unreliable,
+                length: bytes.len() as u16,
+                mindex: 0,
+                sindex: 0,
+                oindex: 0,
+                fragment: None,
+            },
+            data: bytes,
+        };
+        let buff = self.datagram(&[out])?;
+        self.send_raw_to(&buff, address).await?;
+        self.stats.frames_sent += 1;
+        self.sequence += 1;
+        Ok(())
+    }
+
+    // Returns the round-trip time measured from the acked datagrams, if any.
+    pub async fn ack(&mut self, ack: Ack) -> std::io::Result<Option<Duration>> {
         let mut ack_cnt = 0;
         let mut sent = None;
         for seq in ack.ack.sequences.0..ack.ack.sequences.1 + 1 {
+            if let Some(mtu) = self.pmtu.on_ack(seq) {
+                self.resize(mtu);
+            }
+            if let Some(probe) = self.small_probe.take_if(|probe| probe.sequence == seq) {
+                self.small_acked = self.small_acked.max(Some(probe.time));
+            }
             if self.sent.contains(&seq) {
                 ack_cnt += 1;
 
@@ -285,11 +492,19 @@ impl DatagramSender {
                 let index = self
                     .buffer
                     .iter()
-                    .position(|(_, x, _)| x.as_ref().unwrap().sequence == seq)
+                    .position(|(_, x, _)| x.as_ref().is_some_and(|x| x.sequence == seq))
                     .unwrap();
 
                 let sent_packet = self.buffer.remove(index).unwrap();
-                sent = Some(sent_packet.1.as_ref().unwrap().time);
+                self.release_fragments(&sent_packet.0);
+                let time = sent_packet.1.as_ref().unwrap().time;
+                sent = Some(time);
+                let length = sent_packet.0.iter().map(OutPacket::length).sum::<usize>();
+                if length <= self.payload_len(BASE_MTU) {
+                    self.small_acked = self.small_acked.max(Some(time));
+                } else {
+                    self.suspected = None;
+                }
 
                 if sent_packet.2.is_some() && self.buffer.iter().all(|p| p.2.is_none()) {
                     self.is_congestion = false;
@@ -299,17 +514,19 @@ impl DatagramSender {
             }
         }
 
-        if let Some(time) = sent {
-            let rtt = Instant::now().duration_since(time);
+        let rtt = sent.map(|time| Instant::now().duration_since(time));
+        if let Some(rtt) = rtt {
             self.cubic.on_ack(ack_cnt, rtt);
 
             self.rto.compute(rtt);
         }
+        self.stats.datagrams_acked += ack_cnt as u64;
 
-        Ok(())
+        Ok(rtt)
     }
 
     pub async fn nack(&mut self, nack: Nack) -> std::io::Result<()> {
+        self.stats.nacks_received += 1;
         let mut sent = None;
         for seq in nack.nack.sequences.0..nack.nack.sequences.1 + 1 {
             if self.sent.contains(&seq) {
@@ -319,16 +536,15 @@ impl DatagramSender {
                 let index = self
                     .buffer
                     .iter()
-                    .position(|(_, x, _)| x.as_ref().unwrap().sequence == seq)
+                    .position(|(_, x, _)| x.as_ref().is_some_and(|x| x.sequence == seq))
                     .unwrap();
 
-                while let Some(out) = self.buffer[index].0.pop() {
-                    if out.frame.reliability.reliable() {
-                        self.buffer[index].0.insert(0, out);
-                    }
-                }
+                self.buffer[index]
+                    .0
+                    .retain(|out| out.frame.reliability.reliable());
                 sent = Some(self.buffer[index].1.as_ref().unwrap().time);
                 self.buffer[index].1 = None;
+                self.stats.retransmissions += 1;
             }
         }
 
@@ -346,6 +562,7 @@ impl DatagramSender {
     }
 
     pub async fn tick(&mut self) -> std::io::Result<bool> {
+        let base_payload_len = self.payload_len(BASE_MTU);
         let now = Instant::now();
         let timeouted = self
             .buffer
@@ -354,8 +571,10 @@ impl DatagramSender {
             .filter(|(_, conf, _)| now.duration_since(conf.as_ref().unwrap().time) > self.rto.rto);
 
         let mut sent = None;
+        let mut suspect = false;
 
         for (stack, conf, count) in timeouted {
+            let length = stack.iter().map(OutPacket::length).sum::<usize>();
             let mut resends = vec![];
             while let Some(out) = stack.pop() {
                 if out.frame.reliability.reliable() {
@@ -371,23 +590,38 @@ impl DatagramSender {
             self.sent.remove(index);
 
             *conf = None;
+            self.stats.retransmissions += 1;
 
             if let Some(count) = count {
-                if *count >= 4 {
+                if *count >= self.max_resends {
                     // connection lost;
                     return Ok(true);
                 }
                 *count += 1;
+                suspect |= *count >= BLACK_HOLE_RESENDS && length > base_payload_len;
             } else {
                 *count = Some(1)
             }
         }
 
+        let mut probe_small = false;
+        if suspect {
+            let since = *self.suspected.get_or_insert(now);
+            if self.small_acked.is_some_and(|small| small >= since) {
+                self.suspected = None;
+                if let Some(mtu) = self.pmtu.on_black_hole() {
+                    self.resize(mtu);
+                }
+            } else {
+                probe_small = self.pmtu.can_fall_back();
+            }
+        }
+
         if let Some(time) = sent {
             if !self.is_congestion {
                 // congestion event.
                 self.cubic.on_congestion_event(time);
-                self.rto.rto = cmp::min(self.rto.rto * 2, MAX_RTO);
+                self.rto.rto = cmp::min(self.rto.rto.saturating_mul(2), self.rto.max);
                 self.is_congestion = true;
             }
 
@@ -398,9 +632,56 @@ impl DatagramSender {
             }
         }
 
+        if probe_small {
+            self.send_small_probe().await?;
+        }
+
+        self.pmtu.check_timeout(now, self.rto.rto);
+        if let Some(size) = self.pmtu.next_probe(now) {
+            self.send_probe(size).await?;
+        }
+
         Ok(false)
     }
 
+    pub fn mtu(&self) -> u16 {
+        self.pmtu.mtu
+    }
+
+    pub fn set_address(&mut self, address: SocketAddr) {
+        self.address = address;
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto.rto
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.rto.rtts.as_ref().map(|rtts| rtts.srtt)
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            ack_latency: self.srtt(),
+            cwnd: self.cubic.cwnd,
+            ssthresh: self.cubic.ssthresh,
+            in_flight: self.sent.len(),
+            send_queue: self.buffer.len(),
+            ..self.stats
+        }
+    }
+
+    pub fn is_flushed(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    // Forgets everything queued or in flight.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.sent.clear();
+        self.splits.clear();
+    }
+
     pub fn set_nodelay(&mut self, nodelay: bool) {
         self.nodelay = nodelay;
     }
@@ -411,9 +692,5 @@ impl DatagramSender {
 }
 
 fn absolute_div(p: Duration, o: Duration) -> Duration {
-    if p > o {
-        p - o
-    } else {
-        o - p
-    }
+    p.abs_diff(o)
 }
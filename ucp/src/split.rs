@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use crate::{
+    DisconnectReason, DropGuard, PacketReceiver, Reliability, Result, Session, UcpSession,
+};
+
+pub struct SendHalf<'a> {
+    conn: &'a Session,
+}
+
+pub struct RecvHalf<'a> {
+    receiver: &'a mut PacketReceiver,
+}
+
+#[derive(Clone)]
+pub struct OwnedSendHalf {
+    conn: Session,
+    _drop_guard: Arc<DropGuard>,
+}
+
+pub struct OwnedRecvHalf {
+    receiver: PacketReceiver,
+    _drop_guard: Arc<DropGuard>,
+}
+
+impl UcpSession {
+    pub fn split(&mut self) -> (SendHalf<'_>, RecvHalf<'_>) {
+        (
+            SendHalf { conn: &self.conn },
+            RecvHalf {
+                receiver: &mut self.receiver,
+            },
+        )
+    }
+
+    pub fn into_split(self) -> (OwnedSendHalf, OwnedRecvHalf) {
+        (
+            OwnedSendHalf {
+                conn: self.conn,
+                _drop_guard: self.drop_guard.clone(),
+            },
+            OwnedRecvHalf {
+                receiver: self.receiver,
+                _drop_guard: self.drop_guard,
+            },
+        )
+    }
+}
+
+impl SendHalf<'_> {
+    pub async fn send(&self, bytes: &[u8], reliability: Reliability) -> Result<()> {
+        self.conn.lock().await.send(bytes, reliability).await
+    }
+}
+
+impl RecvHalf<'_> {
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.receiver.recv().await
+    }
+
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.receiver.reason()
+    }
+}
+
+impl OwnedSendHalf {
+    pub async fn send(&self, bytes: &[u8], reliability: Reliability) -> Result<()> {
+        self.conn.lock().await.send(bytes, reliability).await
+    }
+
+    pub async fn set_nodelay(&self, nodelay: bool) {
+        self.conn.lock().await.set_nodelay(nodelay);
+    }
+
+    pub async fn nodelay(&self) -> bool {
+        self.conn.lock().await.nodelay()
+    }
+}
+
+impl OwnedRecvHalf {
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        self.receiver.recv().await
+    }
+
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.receiver.reason()
+    }
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+/// Snapshot of a session's counters and congestion state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConnectionStats {
+    /// UDP payload bytes, acks and encryption overhead included.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    /// Datagrams acked by the peer.
+    pub datagrams_acked: u64,
+    pub frames_sent: u64,
+    /// Datagrams queued again after a NACK or a retransmission timeout.
+    pub retransmissions: u64,
+    pub nacks_sent: u64,
+    pub nacks_received: u64,
+    /// Smoothed time until datagrams are acked.
+    pub ack_latency: Option<Duration>,
+    /// Congestion window, in datagrams.
+    pub cwnd: u32,
+    pub ssthresh: u32,
+    /// Datagrams sent and not yet acked.
+    pub in_flight: usize,
+    /// Datagrams waiting to be sent or acked.
+    pub send_queue: usize,
+    /// Fragments received for packets that are still incomplete.
+    pub pending_fragments: usize,
+    /// Ordered packets held back until the ones before them arrive.
+    pub out_of_order: usize,
+}
+
+impl ConnectionStats {
+    /// Share of the datagrams that had to be retransmitted, from 0 to 1.
+    pub fn loss_rate(&self) -> f64 {
+        let total = self.datagrams_acked + self.retransmissions;
+        if total == 0 {
+            return 0.;
+        }
+        self.retransmissions as f64 / total as f64
+    }
+}
@@ -1,4 +1,7 @@
-use std::{io::Write, net::SocketAddr};
+use std::{
+    io::{Read, Write},
+    net::SocketAddr,
+};
 
 use packet_derive::*;
 
@@ -19,6 +22,35 @@ pub(crate) fn decode_syspacket<T: SystemPacket>(bytes: &[u8]) -> std::io::Result
     T::decode(&mut reader)
 }
 
+pub(crate) fn decode_packet<T: SystemPacket>(bytes: &[u8]) -> crate::Result<T> {
+    decode_syspacket(bytes).map_err(|_| crate::UcpError::Decode {
+        packet_id: bytes.first().copied().unwrap_or_default(),
+    })
+}
+
+fn remaining(bytes: &CursorReader) -> usize {
+    bytes
+        .get_ref()
+        .len()
+        .saturating_sub(bytes.position() as usize)
+}
+
+// Skips the internal addresses, whose count differs between implementations, up to
+// the two timestamps that end the packet.
+fn skip_to_timestamps(bytes: &mut CursorReader) -> std::io::Result<()> {
+    if remaining(bytes) < 16 {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    }
+    bytes.set_position((bytes.get_ref().len() - 16) as u64);
+    Ok(())
+}
+
+fn decode_key(bytes: &mut CursorReader) -> std::io::Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    bytes.read_exact(&mut key)?;
+    Ok(key)
+}
+
 pub(crate) fn encode_syspacket<T: SystemPacket>(
     packet: T,
     dst: &mut Vec<u8>,
@@ -50,6 +82,19 @@ impl SystemPacket for UnconnectedPing {
     const ID: u8 = 0x1;
 }
 
+#[derive(Den)]
+pub struct UnconnectedPingOpenConnections {
+    #[den(with = "Big")]
+    pub time_stamp: u64,
+    #[den(with = "MAGIC")]
+    pub magic: (),
+    #[den(with = "Big")]
+    pub guid: u64,
+}
+impl SystemPacket for UnconnectedPingOpenConnections {
+    const ID: u8 = 0x2;
+}
+
 #[derive(Den)]
 pub struct ConnectedPong {
     #[den(with = "Big")]
@@ -61,6 +106,13 @@ impl SystemPacket for ConnectedPong {
     const ID: u8 = 0x3;
 }
 
+// Sent reliably to a quiet peer; its ack shows the peer is still there.
+#[derive(Den)]
+pub struct DetectLostConnections {}
+impl SystemPacket for DetectLostConnections {
+    const ID: u8 = 0x4;
+}
+
 pub struct OpenConnectionRequest1 {
     pub magic: (),
     pub protocol_version: u8,
@@ -90,44 +142,169 @@ impl SystemPacket for OpenConnectionRequest1 {
     const ID: u8 = 0x5;
 }
 
-#[derive(Den)]
 pub struct OpenConnectionReply1 {
-    #[den(with = "MAGIC")]
     pub magic: (),
-    #[den(with = "Big")]
     pub guid: u64,
-    pub use_encryption: bool,
-    #[den(with = "Big")]
+    // Present when the server wants it echoed in OpenConnectionRequest2.
+    pub cookie: Option<u32>,
+    // Static key, present when the server offers encryption; always comes with a cookie.
+    pub server_key: Option<[u8; 32]>,
     pub mtu_size: u16,
 }
+impl Den for OpenConnectionReply1 {
+    fn decode(bytes: &mut CursorReader) -> std::io::Result<Self> {
+        MAGIC::decode(bytes)?;
+        let guid = Big::decode(bytes)?;
+        let (cookie, server_key) = if bool::decode(bytes)? {
+            let cookie = Big::decode(bytes)?;
+            // Only told apart by length: the key (32) precedes the mtu (2).
+            let server_key = if remaining(bytes) == 34 {
+                Some(decode_key(bytes)?)
+            } else {
+                None
+            };
+            (Some(cookie), server_key)
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            magic: (),
+            guid,
+            cookie,
+            server_key,
+            mtu_size: Big::decode(bytes)?,
+        })
+    }
+
+    fn encode(&self, bytes: &mut CursorWriter) -> std::io::Result<()> {
+        MAGIC::encode(&self.magic, bytes)?;
+        Big::encode(&self.guid, bytes)?;
+        bool::encode(&self.cookie.is_some(), bytes)?;
+        if let Some(cookie) = &self.cookie {
+            Big::encode(cookie, bytes)?;
+            if let Some(key) = &self.server_key {
+                bytes.write_all(key)?;
+            }
+        }
+        Big::encode(&self.mtu_size, bytes)
+    }
+
+    fn size(&self) -> usize {
+        let security = self
+            .cookie
+            .map(|_| 4 + self.server_key.map(|_| 32).unwrap_or(0))
+            .unwrap_or(0);
+        16 + 8 + 1 + security + 2
+    }
+}
 impl SystemPacket for OpenConnectionReply1 {
     const ID: u8 = 0x6;
 }
 
-#[derive(Den)]
 pub struct OpenConnectionRequest2 {
-    #[den(with = "MAGIC")]
     pub magic: (),
+    pub cookie: Option<u32>,
+    // Ephemeral key of a client that wants encryption; requires a cookie.
+    pub client_key: Option<[u8; 32]>,
     pub address: SocketAddr,
-    #[den(with = "Big")]
     pub mtu: u16,
-    #[den(with = "Big")]
     pub guid: u64,
 }
+impl Den for OpenConnectionRequest2 {
+    fn decode(bytes: &mut CursorReader) -> std::io::Result<Self> {
+        MAGIC::decode(bytes)?;
+        // The cookie (plus the challenge flag) is only told apart by length:
+        // address (7 or 29) + mtu (2) + guid (8) make 17 or 39 bytes without it.
+        let (cookie, client_key) = if !matches!(remaining(bytes), 17 | 39) {
+            let cookie = Big::decode(bytes)?;
+            let client_key = if bool::decode(bytes)? {
+                Some(decode_key(bytes)?)
+            } else {
+                None
+            };
+            (Some(cookie), client_key)
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            magic: (),
+            cookie,
+            client_key,
+            address: SocketAddr::decode(bytes)?,
+            mtu: Big::decode(bytes)?,
+            guid: Big::decode(bytes)?,
+        })
+    }
+
+    fn encode(&self, bytes: &mut CursorWriter) -> std::io::Result<()> {
+        MAGIC::encode(&self.magic, bytes)?;
+        if let Some(cookie) = &self.cookie {
+            Big::encode(cookie, bytes)?;
+            bool::encode(&self.client_key.is_some(), bytes)?;
+            if let Some(key) = &self.client_key {
+                bytes.write_all(key)?;
+            }
+        }
+        SocketAddr::encode(&self.address, bytes)?;
+        Big::encode(&self.mtu, bytes)?;
+        Big::encode(&self.guid, bytes)
+    }
+
+    fn size(&self) -> usize {
+        let security = self
+            .cookie
+            .map(|_| 5 + self.client_key.map(|_| 32).unwrap_or(0))
+            .unwrap_or(0);
+        16 + security + self.address.size() + 2 + 8
+    }
+}
 impl SystemPacket for OpenConnectionRequest2 {
     const ID: u8 = 0x7;
 }
 
-#[derive(Den)]
 pub struct OpenConnectionReply2 {
-    #[den(with = "MAGIC")]
     pub magic: (),
-    #[den(with = "Big")]
     pub guid: u64,
     pub address: SocketAddr,
-    #[den(with = "Big")]
     pub mtu: u16,
-    pub use_encryption: bool,
+    // Ephemeral key of the server, sent when the session is encrypted.
+    pub server_key: Option<[u8; 32]>,
+}
+impl Den for OpenConnectionReply2 {
+    fn decode(bytes: &mut CursorReader) -> std::io::Result<Self> {
+        MAGIC::decode(bytes)?;
+        let guid = Big::decode(bytes)?;
+        let address = SocketAddr::decode(bytes)?;
+        let mtu = Big::decode(bytes)?;
+        let server_key = if bool::decode(bytes)? {
+            Some(decode_key(bytes)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            magic: (),
+            guid,
+            address,
+            mtu,
+            server_key,
+        })
+    }
+
+    fn encode(&self, bytes: &mut CursorWriter) -> std::io::Result<()> {
+        MAGIC::encode(&self.magic, bytes)?;
+        Big::encode(&self.guid, bytes)?;
+        SocketAddr::encode(&self.address, bytes)?;
+        Big::encode(&self.mtu, bytes)?;
+        bool::encode(&self.server_key.is_some(), bytes)?;
+        if let Some(key) = &self.server_key {
+            bytes.write_all(key)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> usize {
+        16 + 8 + self.address.size() + 2 + 1 + self.server_key.map(|_| 32).unwrap_or(0)
+    }
 }
 impl SystemPacket for OpenConnectionReply2 {
     const ID: u8 = 0x8;
@@ -155,9 +332,7 @@ impl Den for ConnectionRequestAccepted {
     fn decode(bytes: &mut CursorReader) -> std::io::Result<Self> {
         let client_address = SocketAddr::decode(bytes)?;
         let system_index = Big::decode(bytes)?;
-        bytes.set_position(
-            bytes.position() + ((bytes.get_ref().len() - 16) - bytes.position() as usize) as u64,
-        );
+        skip_to_timestamps(bytes)?;
         let request_timestamp = Big::decode(bytes)?;
         let accepted_timestamp = Big::decode(bytes)?;
         Ok(Self {
@@ -195,10 +370,7 @@ impl Den for NewIncomingConnections {
         Ok(Self {
             server_address: SocketAddr::decode(bytes)?,
             request_timestamp: {
-                bytes.set_position(
-                    bytes.position()
-                        + ((bytes.get_ref().len() - 16) - bytes.position() as usize) as u64,
-                );
+                skip_to_timestamps(bytes)?;
                 Big::decode(bytes)?
             },
             accepted_timestamp: Big::decode(bytes)?,
@@ -221,12 +393,56 @@ impl SystemPacket for NewIncomingConnections {
     const ID: u8 = 0x13;
 }
 
+#[derive(Den)]
+pub struct ConnectionRequestFailed {
+    #[den(with = "MAGIC")]
+    pub magic: (),
+    #[den(with = "Big")]
+    pub server_guid: u64,
+}
+impl SystemPacket for ConnectionRequestFailed {
+    const ID: u8 = 0x11;
+}
+
+#[derive(Den)]
+pub struct AlreadyConnected {
+    #[den(with = "MAGIC")]
+    pub magic: (),
+    #[den(with = "Big")]
+    pub server_guid: u64,
+}
+impl SystemPacket for AlreadyConnected {
+    const ID: u8 = 0x12;
+}
+
+#[derive(Den)]
+pub struct NoFreeIncomingConnections {
+    #[den(with = "MAGIC")]
+    pub magic: (),
+    #[den(with = "Big")]
+    pub server_guid: u64,
+}
+impl SystemPacket for NoFreeIncomingConnections {
+    const ID: u8 = 0x14;
+}
+
 #[derive(Den)]
 pub struct DisconnectionNotification {}
 impl SystemPacket for DisconnectionNotification {
     const ID: u8 = 0x15;
 }
 
+#[derive(Den)]
+pub struct ConnectionBanned {
+    #[den(with = "MAGIC")]
+    pub magic: (),
+    #[den(with = "Big")]
+    pub server_guid: u64,
+}
+impl SystemPacket for ConnectionBanned {
+    const ID: u8 = 0x17;
+}
+
 #[derive(Den)]
 pub struct UnconnectedPong {
     #[den(with = "Big")]
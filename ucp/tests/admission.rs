@@ -0,0 +1,75 @@
+use std::{net::IpAddr, thread::sleep, time::Duration};
+
+use ucp::{BanList, IpCidr};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn cidr(s: &str) -> IpCidr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn prefixes() {
+    let subnet = cidr("192.168.1.0/24");
+    assert!(subnet.contains(ip("192.168.1.200")));
+    assert!(!subnet.contains(ip("192.168.2.1")));
+
+    assert!(cidr("0.0.0.0/0").contains(ip("203.0.113.7")));
+    assert!(!cidr("0.0.0.0/0").contains(ip("2001:db8::1")));
+    assert!(cidr("::/0").contains(ip("2001:db8::1")));
+    assert!(cidr("::/0").contains(ip("203.0.113.7")));
+
+    // A bare address is a host route, whatever the family.
+    assert_eq!(cidr("10.0.0.1"), IpCidr::new(ip("10.0.0.1"), 32));
+    assert_eq!(IpCidr::new(ip("10.0.0.1"), 128), cidr("10.0.0.1/32"));
+    assert!(!cidr("10.0.0.1").contains(ip("10.0.0.2")));
+
+    assert!("10.0.0.0/x".parse::<IpCidr>().is_err());
+    assert!("not an address".parse::<IpCidr>().is_err());
+}
+
+#[test]
+fn ipv4_mapped() {
+    assert_eq!(cidr("::ffff:10.0.0.0/104"), cidr("10.0.0.0/8"));
+    assert_eq!(IpCidr::from(ip("::ffff:10.0.0.1")), cidr("10.0.0.1/32"));
+
+    let mapped = cidr("::ffff:10.0.0.0/104");
+    assert!(mapped.contains(ip("10.1.2.3")));
+    assert!(mapped.contains(ip("::ffff:10.1.2.3")));
+    assert!(!mapped.contains(ip("11.0.0.1")));
+    assert!(cidr("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+}
+
+#[test]
+fn ban_expiry() {
+    let bans = BanList::new();
+    bans.ban(ip("10.0.0.1"), Some(Duration::from_millis(50)));
+    bans.ban(cidr("10.1.0.0/16"), None);
+    assert!(bans.is_banned(ip("10.0.0.1")));
+    assert!(bans.is_banned(ip("10.1.2.3")));
+
+    sleep(Duration::from_millis(100));
+    assert!(!bans.is_banned(ip("10.0.0.1")));
+    assert!(bans.is_banned(ip("10.1.2.3")));
+}
+
+#[test]
+fn unban() {
+    let bans = BanList::new();
+    bans.ban(cidr("10.1.0.0/16"), None);
+    // Only the exact network comes off the list.
+    assert!(!bans.unban(cidr("10.1.2.0/24")));
+    assert!(bans.is_banned(ip("10.1.2.3")));
+
+    assert!(bans.unban(cidr("10.1.0.0/16")));
+    assert!(!bans.is_banned(ip("10.1.2.3")));
+    assert!(!bans.unban(cidr("10.1.0.0/16")));
+
+    // Banning again replaces the expiry instead of adding an entry.
+    bans.ban(ip("10.0.0.1"), None);
+    bans.ban(ip("10.0.0.1"), Some(Duration::from_secs(60)));
+    assert!(bans.unban(ip("10.0.0.1")));
+    assert!(!bans.is_banned(ip("10.0.0.1")));
+}
@@ -0,0 +1,160 @@
+// Each test binary uses only some of these.
+#![allow(dead_code)]
+
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ucp::{Reliability, UcpConfig, UcpListener, UcpSession};
+
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// Fails the test if `future` takes longer than [`TIMEOUT`].
+pub async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(TIMEOUT, future)
+        .await
+        .expect("timed out")
+}
+
+pub async fn listener() -> UcpListener {
+    listener_with(UcpConfig::new()).await
+}
+
+pub async fn listener_with(config: UcpConfig) -> UcpListener {
+    UcpListener::bind_with("127.0.0.1:0", 0x1, "title".to_owned(), config)
+        .await
+        .unwrap()
+}
+
+/// Connects a client with `guid` to `listener` and accepts it, returning the client
+/// and the server's session.
+pub async fn connect(listener: &mut UcpListener, guid: u64) -> (UcpSession, UcpSession) {
+    let remote = listener.local_addr().unwrap();
+    let (client, session) = within(async {
+        tokio::join!(
+            UcpSession::connect("127.0.0.1:0", remote, guid),
+            listener.accept()
+        )
+    })
+    .await;
+    (client.unwrap(), session.unwrap())
+}
+
+pub async fn echo_server(listen: &str) -> SocketAddr {
+    echo_server_with(listen, UcpConfig::new()).await
+}
+
+pub async fn echo_server_with(listen: &str, config: UcpConfig) -> SocketAddr {
+    let listener = UcpListener::bind_with(listen, 0x1, "title".to_owned(), config)
+        .await
+        .unwrap();
+    run_echo(listener)
+}
+
+pub fn run_echo(mut listener: UcpListener) -> SocketAddr {
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let Ok(mut session) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(async move {
+                while let Ok(packet) = session.recv().await {
+                    session
+                        .send(&packet, Reliability::ReliableOrdered)
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+    });
+    addr
+}
+
+/// Sends `payload` from `client` and checks that it comes back.
+pub async fn assert_echo(client: &mut UcpSession, payload: &[u8]) {
+    client
+        .send(payload, Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(client.recv()).await.unwrap(), payload);
+}
+
+// OpenConnectionRequest2 without a cookie, for a 127.0.0.1 server.
+pub fn raw_ocrequest2(server: SocketAddr) -> Vec<u8> {
+    raw_ocrequest2_with_mtu(server, 1400)
+}
+
+pub fn raw_ocrequest2_with_mtu(server: SocketAddr, mtu: u16) -> Vec<u8> {
+    [
+        &[0x07][..],
+        &MAGIC,
+        &[4, !127, !0, !0, !1],
+        &server.port().to_be_bytes(),
+        &mtu.to_be_bytes(),
+        &3u64.to_be_bytes(),
+    ]
+    .concat()
+}
+
+// A datagram carrying `payload` in one unreliable frame.
+pub fn raw_datagram(sequence: u8, payload: &[u8]) -> Vec<u8> {
+    let bits = (payload.len() as u16 * 8).to_be_bytes();
+    [&[0x84, sequence, 0, 0, 0x00, bits[0], bits[1]][..], payload].concat()
+}
+
+// UnconnectedPing (0x01) or UnconnectedPingOpenConnections (0x02).
+pub async fn raw_ping(id: u8, server: SocketAddr) -> Option<Vec<u8>> {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let ping = [&[id][..], &7u64.to_be_bytes(), &MAGIC, &9u64.to_be_bytes()].concat();
+    socket.send_to(&ping, server).await.unwrap();
+    let mut v = [0u8; 2048];
+    let received = tokio::time::timeout(Duration::from_millis(300), socket.recv_from(&mut v));
+    let (size, _) = received.await.ok()?.unwrap();
+    Some(v[..size].to_vec())
+}
+
+// Relays one client to `server`, dropping datagrams larger than `limit` both ways.
+// Returns the relay address and the largest datagram it passed on to the server.
+pub async fn lossy_proxy(
+    server: SocketAddr,
+    limit: Arc<AtomicUsize>,
+) -> (SocketAddr, Arc<AtomicUsize>) {
+    let front = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let back = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = front.local_addr().unwrap();
+    let largest = Arc::new(AtomicUsize::new(0));
+    let forwarded = largest.clone();
+    tokio::spawn(async move {
+        let mut client = None;
+        let mut up = [0u8; 2048];
+        let mut down = [0u8; 2048];
+        loop {
+            tokio::select! {
+                Ok((size, src)) = front.recv_from(&mut up) => {
+                    client = Some(src);
+                    if size <= limit.load(Ordering::Relaxed) {
+                        forwarded.fetch_max(size, Ordering::Relaxed);
+                        back.send_to(&up[..size], server).await.ok();
+                    }
+                }
+                Ok((size, _)) = back.recv_from(&mut down) => {
+                    if let Some(client) = client.filter(|_| size <= limit.load(Ordering::Relaxed)) {
+                        front.send_to(&down[..size], client).await.ok();
+                    }
+                }
+            }
+        }
+    });
+    (addr, largest)
+}
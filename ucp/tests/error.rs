@@ -0,0 +1,15 @@
+use std::io::ErrorKind;
+
+use ucp::{DisconnectReason, UcpError};
+
+#[test]
+fn into_io_error() {
+    let os = std::io::Error::from_raw_os_error(98);
+    let kind = os.kind();
+    let io: std::io::Error = UcpError::Io(os).into();
+    assert_eq!(io.raw_os_error(), Some(98));
+    assert_eq!(io.kind(), kind);
+
+    let io: std::io::Error = UcpError::Disconnected(DisconnectReason::RemoteClosed).into();
+    assert_eq!(io.kind(), ErrorKind::ConnectionReset);
+}
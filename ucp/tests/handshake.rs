@@ -0,0 +1,135 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{
+    assert_echo, echo_server, echo_server_with, listener, listener_with, raw_datagram,
+    raw_ocrequest2, within, MAGIC,
+};
+use ucp::{ServerKey, UcpConfig, UcpError, UcpSession};
+
+#[tokio::test]
+async fn handshake_timeout() {
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let config = UcpConfig::new()
+        .handshake_attempts(1)
+        .handshake_retry_interval(Duration::from_millis(50));
+    let result =
+        UcpSession::connect_with("127.0.0.1:0", silent.local_addr().unwrap(), 0x2, config).await;
+    assert!(matches!(result, Err(UcpError::HandshakeTimeout)));
+}
+
+#[tokio::test]
+async fn rejected() {
+    let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let remote = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut v = [0u8; 2048];
+        let (_, src) = server.recv_from(&mut v).await.unwrap();
+        let reply = [&[0x17][..], &MAGIC, &[0; 8]].concat();
+        server.send_to(&reply, src).await.unwrap();
+    });
+    let result = UcpSession::connect("127.0.0.1:0", remote, 0x2).await;
+    assert!(matches!(result, Err(UcpError::ConnectionBanned)));
+}
+
+#[tokio::test]
+async fn undersized_mtu_reply() {
+    let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let remote = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut v = [0u8; 2048];
+        let (_, src) = server.recv_from(&mut v).await.unwrap();
+        let reply = [&[0x06][..], &MAGIC, &[0; 8], &[0], &10u16.to_be_bytes()].concat();
+        server.send_to(&reply, src).await.unwrap();
+    });
+    let result = UcpSession::connect("127.0.0.1:0", remote, 0x2).await;
+    assert!(matches!(result, Err(UcpError::Decode { packet_id: 0x06 })));
+}
+
+#[tokio::test]
+async fn short_new_incoming_connections() {
+    let mut listener = listener().await;
+    let server = listener.local_addr().unwrap();
+    let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    raw.send_to(&raw_ocrequest2(server), server).await.unwrap();
+    let mut v = [0u8; 2048];
+    let (_, _) = raw.recv_from(&mut v).await.unwrap();
+    assert_eq!(v[0], 0x08);
+
+    let request = [&[0x09][..], &3u64.to_be_bytes(), &0u64.to_be_bytes(), &[0]].concat();
+    raw.send_to(&raw_datagram(0, &request), server)
+        .await
+        .unwrap();
+    // No room for the two timestamps after the address.
+    let new_incoming = [0x13, 4, !127, !0, !0, !1, 0x4a, 0xbc, 0];
+    raw.send_to(&raw_datagram(1, &new_incoming), server)
+        .await
+        .unwrap();
+
+    let session = within(listener.accept()).await.unwrap();
+    assert_eq!(session.peer_guid(), 0x3);
+    assert_eq!(session.observed_addr(), None);
+}
+
+#[tokio::test]
+async fn handshake_cookies() {
+    let listener = listener_with(UcpConfig::new().handshake_cookies(true)).await;
+    let remote = listener.local_addr().unwrap();
+
+    let spoofed = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    spoofed
+        .send_to(&raw_ocrequest2(remote), remote)
+        .await
+        .unwrap();
+    let _client = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    assert_eq!(listener.connection_count(), 1);
+}
+
+#[tokio::test]
+async fn encryption() {
+    let key = ServerKey::generate();
+    let config = UcpConfig::new()
+        .server_key(key.clone())
+        .require_encryption(true);
+    let remote = echo_server_with("127.0.0.1:0", config).await;
+
+    let config = UcpConfig::new().pin_server_key(key.public_key());
+    let mut client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, config)
+        .await
+        .unwrap();
+    assert!(client.is_encrypted().await);
+    assert_echo(&mut client, &[0xfe; 4096]).await;
+
+    let plain = UcpSession::connect("127.0.0.1:0", remote, 0x3).await;
+    assert!(matches!(plain, Err(UcpError::ConnectionRequestFailed)));
+
+    let config = UcpConfig::new().pin_server_key(ServerKey::generate().public_key());
+    let impostor = UcpSession::connect_with("127.0.0.1:0", remote, 0x4, config).await;
+    assert!(matches!(impostor, Err(UcpError::UntrustedServerKey)));
+
+    let plain_server = echo_server("127.0.0.1:0").await;
+    let config = UcpConfig::new().use_encryption(true);
+    let result = UcpSession::connect_with("127.0.0.1:0", plain_server, 0x5, config).await;
+    assert!(matches!(result, Err(UcpError::EncryptionUnsupported)));
+}
+
+#[tokio::test]
+async fn optional_encryption() {
+    let key = ServerKey::generate();
+    let remote = echo_server_with("127.0.0.1:0", UcpConfig::new().server_key(key.clone())).await;
+
+    let config = UcpConfig::new().pin_server_key(key.public_key());
+    let encrypted = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, config)
+        .await
+        .unwrap();
+    assert!(encrypted.is_encrypted().await);
+
+    let mut plain = UcpSession::connect("127.0.0.1:0", remote, 0x3)
+        .await
+        .unwrap();
+    assert!(!plain.is_encrypted().await);
+    assert_echo(&mut plain, &[0xfe; 16]).await;
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use tokio_stream::StreamExt;
+
+use common::{
+    connect, echo_server_with, listener, listener_with, raw_ocrequest2, raw_ping, run_echo, within,
+};
+use ucp::{
+    AdmissionPolicy, Decision, DisconnectReason, DuplicatePolicy, Reliability, UcpConfig, UcpError,
+    UcpListener, UcpSession,
+};
+
+#[tokio::test]
+async fn progress_without_accept() {
+    let mut listener = listener().await;
+    // Accept once, then stop; the listener must keep feeding the session anyway.
+    let (client, mut session) = connect(&mut listener, 0x2).await;
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(session.recv()).await.unwrap(), vec![0xfe; 4096]);
+}
+
+#[tokio::test]
+async fn incoming() {
+    let config = UcpConfig::new().accept_timeout(Duration::from_millis(200));
+    let mut listener = listener_with(config).await;
+    let remote = listener.local_addr().unwrap();
+
+    // OpenConnectionRequest2 without a following ConnectionRequest.
+    let stalled = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    stalled
+        .send_to(&raw_ocrequest2(remote), remote)
+        .await
+        .unwrap();
+    let _client = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+
+    let mut incoming = listener.incoming();
+    let first = incoming.next().await.unwrap().unwrap();
+    assert_eq!(first.peer_guid(), 0x2);
+    // The stalled handshake times out without surfacing.
+    let stalled = tokio::time::timeout(Duration::from_millis(500), incoming.next()).await;
+    assert!(stalled.is_err());
+
+    let (_client, second) = tokio::join!(
+        UcpSession::connect("127.0.0.1:0", remote, 0x3),
+        incoming.next()
+    );
+    assert_eq!(second.unwrap().unwrap().peer_guid(), 0x3);
+}
+
+#[tokio::test]
+async fn unread_session() {
+    let mut listener = listener_with(UcpConfig::new().session_channel_capacity(1)).await;
+    let (client, mut unread) = connect(&mut listener, 0x2).await;
+    for i in 0..64u8 {
+        client
+            .send(&[0xf0, i], Reliability::ReliableOrdered)
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Nobody reads `unread`, which must not hold up the listener.
+    connect(&mut listener, 0x3).await;
+
+    for i in 0..64u8 {
+        let got = tokio::time::timeout(Duration::from_secs(10), unread.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(got, vec![0xf0, i]);
+    }
+}
+
+#[tokio::test]
+async fn server_full() {
+    let remote = echo_server_with("127.0.0.1:0", UcpConfig::new().max_connections(1)).await;
+    let _first = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    let second = UcpSession::connect("127.0.0.1:0", remote, 0x3).await;
+    assert!(matches!(second, Err(UcpError::NoFreeIncomingConnections)));
+}
+
+#[tokio::test]
+async fn ping_open_connections() {
+    let remote = echo_server_with("127.0.0.1:0", UcpConfig::new().max_connections(1)).await;
+    let pong = raw_ping(0x02, remote).await.unwrap();
+    assert_eq!(pong[0], 0x1c);
+    assert_eq!(&pong[1..9], &7u64.to_be_bytes());
+
+    let _first = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    assert_eq!(raw_ping(0x02, remote).await, None);
+    // Plain pings are answered either way.
+    assert_eq!(raw_ping(0x01, remote).await.unwrap()[0], 0x1c);
+}
+
+#[tokio::test]
+async fn duplicate_guid() {
+    let listener = listener().await;
+    let remote = listener.local_addr().unwrap();
+
+    // A repeated request gets the same reply instead of AlreadyConnected, even after
+    // other datagrams; only a ConnectionRequest proves the first reply arrived.
+    let pending = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut replies = vec![];
+    for _ in 0..2 {
+        pending.send_to(&[0x84, 0, 0, 0], remote).await.unwrap();
+        pending
+            .send_to(&raw_ocrequest2(remote), remote)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 2048];
+        let (size, _) = pending.recv_from(&mut buf).await.unwrap();
+        replies.push(buf[..size].to_vec());
+    }
+    assert_eq!(replies[0][0], 0x08);
+    assert_eq!(replies[0], replies[1]);
+    assert_eq!(listener.connection_count(), 1);
+
+    // raw_ocrequest2 uses GUID 3.
+    let duplicate = UcpSession::connect("127.0.0.1:0", remote, 0x3).await;
+    assert!(matches!(duplicate, Err(UcpError::AlreadyConnected)));
+    assert_eq!(listener.connection_count(), 1);
+}
+
+#[tokio::test]
+async fn ended_duplicate() {
+    let mut listener = listener_with(UcpConfig::new().max_connections(1)).await;
+    let (first, mut old) = connect(&mut listener, 0x2).await;
+    first.close().await.unwrap();
+    assert!(within(old.recv()).await.is_err());
+
+    // `old` is still held, but has ended, so it neither blocks the GUID nor takes
+    // the only slot.
+    assert_eq!(listener.connection_count(), 0);
+    let (_second, new) = connect(&mut listener, 0x2).await;
+    assert_eq!(new.peer_guid(), 0x2);
+}
+
+#[tokio::test]
+async fn duplicate_guid_kicks_existing() {
+    let config = UcpConfig::new().duplicate_policy(DuplicatePolicy::KickExisting);
+    let mut listener = listener_with(config).await;
+    let (mut first, old) = connect(&mut listener, 0x2).await;
+
+    let (_second, new) = connect(&mut listener, 0x2).await;
+    assert_eq!(old.disconnect_reason(), Some(DisconnectReason::Kicked));
+    assert!(matches!(
+        within(first.recv()).await,
+        Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+    ));
+    assert_eq!(new.peer_guid(), 0x2);
+    assert_eq!(listener.connection_count(), 1);
+}
+
+#[tokio::test]
+async fn banned() {
+    let listener = listener().await;
+    listener.ban("127.0.0.0/8".parse::<ucp::IpCidr>().unwrap(), None);
+    let bans = listener.ban_list();
+    let remote = run_echo(listener);
+    let result = UcpSession::connect("127.0.0.1:0", remote, 0x2).await;
+    assert!(matches!(result, Err(UcpError::ConnectionBanned)));
+
+    assert!(bans.unban("127.0.0.0/8".parse::<ucp::IpCidr>().unwrap()));
+    UcpSession::connect("127.0.0.1:0", remote, 0x3)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn kick() {
+    // Dual-stack, so the IPv4 client is seen at an IPv4-mapped address.
+    let mut listener = UcpListener::bind("[::]:0", 0x1, "title".to_owned())
+        .await
+        .unwrap();
+    let remote = SocketAddr::from(([127, 0, 0, 1], listener.local_addr().unwrap().port()));
+    let mut client = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    let session = listener.accept().await.unwrap();
+    assert_eq!(session.peer_addr(), client.local_addr());
+
+    assert!(!listener.kick("127.0.0.1:1".parse().unwrap()).await.unwrap());
+    assert!(listener.kick(client.local_addr()).await.unwrap());
+    assert!(matches!(
+        within(client.recv()).await,
+        Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+    ));
+    drop(session);
+    within(async {
+        while listener.connection_count() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    // The address is free again.
+    assert!(!listener.kick(client.local_addr()).await.unwrap());
+}
+
+struct RejectGuid(u64);
+
+impl AdmissionPolicy for RejectGuid {
+    fn on_open_connection(&self, _: SocketAddr, _: Option<u64>, _: u16) -> Decision {
+        Decision::Accept
+    }
+
+    fn on_connection_request(&self, _: SocketAddr, guid: u64) -> Decision {
+        if guid == self.0 {
+            Decision::Reject
+        } else {
+            Decision::Accept
+        }
+    }
+}
+
+#[tokio::test]
+async fn admission_policy() {
+    let listener = listener().await;
+    listener.set_admission_policy(RejectGuid(0x3));
+    let remote = run_echo(listener);
+    let _accepted = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    let rejected = UcpSession::connect("127.0.0.1:0", remote, 0x3).await;
+    assert!(matches!(rejected, Err(UcpError::ConnectionRequestFailed)));
+}
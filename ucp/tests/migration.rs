@@ -0,0 +1,148 @@
+mod common;
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::sync::Notify;
+
+use common::{listener_with, within};
+use ucp::{Reliability, ServerKey, UcpConfig, UcpListener, UcpSession};
+
+// Relays one client to `server`, moving to a new source port whenever `rebind` is
+// notified, like a NAT that lost its mapping.
+async fn rebinding_proxy(server: SocketAddr, rebind: Arc<Notify>) -> SocketAddr {
+    let front = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut back = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = front.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut client = None;
+        let mut up = [0u8; 2048];
+        let mut down = [0u8; 2048];
+        loop {
+            tokio::select! {
+                Ok((size, src)) = front.recv_from(&mut up) => {
+                    client = Some(src);
+                    back.send_to(&up[..size], server).await.ok();
+                }
+                Ok((size, _)) = back.recv_from(&mut down) => {
+                    if let Some(client) = client {
+                        front.send_to(&down[..size], client).await.ok();
+                    }
+                }
+                _ = rebind.notified() => {
+                    back = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                }
+            }
+        }
+    });
+    addr
+}
+
+// Forwards between a client and `server`. Once `replay` fires, the next client datagram
+// also reaches the server first from another port, which never answers.
+async fn replaying_proxy(server: SocketAddr, replay: Arc<Notify>) -> SocketAddr {
+    let front = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let back = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let decoy = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = front.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut client = None;
+        let mut armed = false;
+        let mut up = [0u8; 2048];
+        let mut down = [0u8; 2048];
+        loop {
+            tokio::select! {
+                Ok((size, src)) = front.recv_from(&mut up) => {
+                    client = Some(src);
+                    if std::mem::take(&mut armed) {
+                        decoy.send_to(&up[..size], server).await.ok();
+                    }
+                    back.send_to(&up[..size], server).await.ok();
+                }
+                Ok((size, _)) = back.recv_from(&mut down) => {
+                    if let Some(client) = client {
+                        front.send_to(&down[..size], client).await.ok();
+                    }
+                }
+                _ = replay.notified() => armed = true,
+            }
+        }
+    });
+    addr
+}
+
+// Connects a client that pins `key` to `listener` through `remote` and accepts it.
+async fn migrating_pair(
+    listener: &mut UcpListener,
+    key: &ServerKey,
+    remote: SocketAddr,
+) -> (UcpSession, UcpSession) {
+    let config = UcpConfig::new().pin_server_key(key.public_key());
+    let (client, session) = within(async {
+        tokio::join!(
+            UcpSession::connect_with("127.0.0.1:0", remote, 0x2, config),
+            listener.accept()
+        )
+    })
+    .await;
+    (client.unwrap(), session.unwrap())
+}
+
+fn migrating_config(key: &ServerKey) -> UcpConfig {
+    UcpConfig::new()
+        .server_key(key.clone())
+        .connection_migration(true)
+}
+
+#[tokio::test]
+async fn connection_migration() {
+    let key = ServerKey::generate();
+    let mut listener = listener_with(migrating_config(&key)).await;
+    let rebind = Arc::new(Notify::new());
+    let remote = rebinding_proxy(listener.local_addr().unwrap(), rebind.clone()).await;
+    let (mut client, mut session) = migrating_pair(&mut listener, &key, remote).await;
+    let before = session.peer_addr();
+    // Let the handshake get acked through the old mapping first.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    rebind.notify_one();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(session.recv()).await.unwrap(), vec![0xfe; 4096]);
+    assert_ne!(session.peer_addr(), before);
+    assert_eq!(listener.connection_count(), 1);
+
+    session
+        .send(&[0xfd; 16], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(client.recv()).await.unwrap(), vec![0xfd; 16]);
+}
+
+#[tokio::test]
+async fn replayed_from_other_port() {
+    let key = ServerKey::generate();
+    let mut listener = listener_with(migrating_config(&key)).await;
+    let replay = Arc::new(Notify::new());
+    let remote = replaying_proxy(listener.local_addr().unwrap(), replay.clone()).await;
+    let (mut client, mut session) = migrating_pair(&mut listener, &key, remote).await;
+    let before = session.peer_addr();
+
+    replay.notify_one();
+    client
+        .send(&[0xfe; 16], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(session.recv()).await.unwrap(), vec![0xfe; 16]);
+    // The decoy never answers the challenge, so the session stays where it was.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(session.peer_addr(), before);
+
+    session
+        .send(&[0xfd; 16], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    assert_eq!(within(client.recv()).await.unwrap(), vec![0xfd; 16]);
+}
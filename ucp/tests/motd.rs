@@ -0,0 +1,27 @@
+use ucp::BedrockMotd;
+
+const DEDICATED: &str =
+    "MCPE;Dedicated Server;390;1.14.60;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+#[test]
+fn roundtrip() {
+    let motd: BedrockMotd = DEDICATED.parse().unwrap();
+    assert_eq!(motd.edition, "MCPE");
+    assert_eq!(motd.protocol, 390);
+    assert_eq!(motd.max_players, 10);
+    assert_eq!(motd.server_id, Some(13253860892328930865));
+    assert_eq!(motd.level_name.as_deref(), Some("Bedrock level"));
+    assert_eq!(motd.port_v6, Some(19133));
+    assert_eq!(motd.to_string(), DEDICATED);
+}
+
+#[test]
+fn missing_trailing_fields() {
+    let motd: BedrockMotd = "MCPE;Old Server;137;1.2.0;3;20".parse().unwrap();
+    assert_eq!(motd.online_players, 3);
+    assert_eq!(motd.server_id, None);
+    assert_eq!(motd.port_v4, None);
+    assert_eq!(motd.to_string(), "MCPE;Old Server;137;1.2.0;3;20;");
+
+    assert!("MCPE;Broken;137".parse::<BedrockMotd>().is_err());
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::{echo_server_with, lossy_proxy};
+use ucp::{Reliability, UcpConfig, UcpSession, MAX_MTU_SIZE};
+
+fn fast_retransmit() -> UcpConfig {
+    UcpConfig::new()
+        .min_rto(Duration::from_millis(50))
+        .max_rto(Duration::from_millis(200))
+        .handshake_attempts(2)
+        .handshake_retry_interval(Duration::from_millis(100))
+}
+
+#[tokio::test]
+async fn black_hole() {
+    let server = echo_server_with("127.0.0.1:0", fast_retransmit()).await;
+    let limit = Arc::new(AtomicUsize::new(usize::MAX));
+    let (remote, _) = lossy_proxy(server, limit.clone()).await;
+    let mut client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, fast_retransmit())
+        .await
+        .unwrap();
+
+    limit.store(700, Ordering::Relaxed);
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    let echoed = tokio::time::timeout(Duration::from_secs(10), client.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(echoed, vec![0xfe; 4096]);
+}
+
+#[tokio::test]
+async fn mtu_probing() {
+    let server = echo_server_with("127.0.0.1:0", fast_retransmit()).await;
+    let limit = Arc::new(AtomicUsize::new(usize::MAX));
+    let (remote, _) = lossy_proxy(server, limit.clone()).await;
+    let mut client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, fast_retransmit())
+        .await
+        .unwrap();
+    assert_eq!(client.mtu().await, MAX_MTU_SIZE);
+
+    // Small datagrams still get through, so the lost large ones are a black hole.
+    limit.store(1000, Ordering::Relaxed);
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    client.send(&[0xfd], Reliability::Reliable).await.unwrap();
+    for _ in 0..2 {
+        tokio::time::timeout(Duration::from_secs(10), client.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    // Probing climbs back as far as the path allows.
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while client.mtu().await < 950 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(client.mtu().await <= 1032);
+}
+
+#[tokio::test]
+async fn outage_is_not_a_black_hole() {
+    let config = fast_retransmit().max_resends(12);
+    let server = echo_server_with("127.0.0.1:0", config.clone()).await;
+    let limit = Arc::new(AtomicUsize::new(usize::MAX));
+    let (remote, _) = lossy_proxy(server, limit.clone()).await;
+    let mut client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, config)
+        .await
+        .unwrap();
+
+    // Let the handshake settle, then lose everything for a while, small datagrams
+    // included.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    limit.store(0, Ordering::Relaxed);
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    limit.store(usize::MAX, Ordering::Relaxed);
+    let echoed = tokio::time::timeout(Duration::from_secs(10), client.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(echoed, vec![0xfe; 4096]);
+    assert_eq!(client.mtu().await, MAX_MTU_SIZE);
+}
+
+#[tokio::test]
+async fn mtu_probing_stops_at_negotiated() {
+    let server = echo_server_with("127.0.0.1:0", fast_retransmit().max_mtu_size(800)).await;
+    let limit = Arc::new(AtomicUsize::new(usize::MAX));
+    let (remote, largest) = lossy_proxy(server, limit).await;
+    let client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, fast_retransmit())
+        .await
+        .unwrap();
+    assert_eq!(client.mtu().await, 800);
+    // Forget the handshake, which tried larger sizes.
+    largest.store(0, Ordering::Relaxed);
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(largest.load(Ordering::Relaxed) <= 800);
+    assert_eq!(client.mtu().await, 800);
+}
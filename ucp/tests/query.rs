@@ -0,0 +1,56 @@
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use tokio_stream::StreamExt;
+
+use common::{echo_server, listener, run_echo};
+use ucp::{UcpError, UcpListener, UcpSession};
+
+#[tokio::test]
+async fn ping() {
+    let remote = echo_server("127.0.0.1:0").await;
+    let pong = ucp::ping(remote, Duration::from_secs(2)).await.unwrap();
+    assert_eq!(pong.guid, 0x1);
+    assert_eq!(pong.motd, "title");
+    assert!(pong.latency < Duration::from_secs(2));
+
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let result = ucp::ping(silent.local_addr().unwrap(), Duration::from_millis(100)).await;
+    assert!(matches!(result, Err(UcpError::Timeout)));
+}
+
+#[tokio::test]
+async fn motd_provider() {
+    let listener = listener().await;
+    listener
+        .set_motd_provider(|_: SocketAddr, connections: usize| format!("players {}", connections));
+    let remote = run_echo(listener);
+    let pong = ucp::ping(remote, Duration::from_secs(2)).await.unwrap();
+    assert_eq!(pong.motd, "players 0");
+
+    let _session = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    let pong = ucp::ping(remote, Duration::from_secs(2)).await.unwrap();
+    assert_eq!(pong.motd, "players 1");
+}
+
+#[tokio::test]
+async fn discover() {
+    let listener = UcpListener::bind("127.0.0.1:0", 0x5eed, "discoverable".to_owned())
+        .await
+        .unwrap();
+    let remote = run_echo(listener);
+
+    // Pings go to every target, so the server answers twice but is reported once.
+    let found: Vec<_> = ucp::discover_at([remote, remote], Duration::from_millis(300))
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].guid, 0x5eed);
+    assert_eq!(found[0].motd, "discoverable");
+    assert_eq!(found[0].addr, remote);
+}
@@ -0,0 +1,201 @@
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::{
+    assert_echo, connect, echo_server, echo_server_with, listener, lossy_proxy, raw_ocrequest2,
+    raw_ocrequest2_with_mtu, within,
+};
+use ucp::{DisconnectReason, Reliability, Shutdown, UcpConfig, UcpError, UcpSession, MAX_MTU_SIZE};
+
+async fn echo_once(listen: &str, local: &str, remote: &str) {
+    let port = echo_server(listen).await.port();
+    let mut remote: SocketAddr = remote.parse().unwrap();
+    remote.set_port(port);
+
+    let mut client = UcpSession::connect(local, remote, 0x2).await.unwrap();
+    assert_echo(&mut client, &[0xfe; 4096]).await;
+}
+
+#[tokio::test]
+async fn ipv4() {
+    echo_once("127.0.0.1:0", "127.0.0.1:0", "127.0.0.1:0").await;
+}
+
+#[tokio::test]
+async fn ipv6() {
+    echo_once("[::1]:0", "[::1]:0", "[::1]:0").await;
+}
+
+#[tokio::test]
+async fn dual_stack() {
+    echo_once("[::]:0", "127.0.0.1:0", "127.0.0.1:0").await;
+    echo_once("[::]:0", "[::]:0", "127.0.0.1:0").await;
+}
+
+#[tokio::test]
+async fn close() {
+    let mut listener = listener().await;
+    let (client, mut session) = connect(&mut listener, 0x2).await;
+
+    assert_eq!(client.close().await.unwrap(), Shutdown::Clean);
+    assert!(matches!(
+        within(session.recv()).await,
+        Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+    ));
+}
+
+#[tokio::test]
+async fn disconnect_reason() {
+    let mut listener = listener().await;
+    let (mut kicked, mut session) = connect(&mut listener, 0x2).await;
+    let (mut shut_down, other) = connect(&mut listener, 0x3).await;
+
+    assert!(listener.kick(session.peer_addr()).await.unwrap());
+    assert_eq!(session.disconnect_reason(), Some(DisconnectReason::Kicked));
+    assert!(matches!(
+        session.recv().await,
+        Err(UcpError::Disconnected(DisconnectReason::Kicked))
+    ));
+    for _ in 0..2 {
+        assert!(matches!(
+            within(kicked.recv()).await,
+            Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+        ));
+    }
+    assert!(matches!(
+        session.send(&[0xf0], Reliability::Reliable).await,
+        Err(UcpError::Disconnected(DisconnectReason::Kicked))
+    ));
+    let (send_half, _) = kicked.split();
+    assert!(matches!(
+        send_half.send(&[0xf0], Reliability::Reliable).await,
+        Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+    ));
+
+    drop(listener);
+    assert!(matches!(
+        within(shut_down.recv()).await,
+        Err(UcpError::Disconnected(DisconnectReason::RemoteClosed))
+    ));
+    assert_eq!(
+        other.disconnect_reason(),
+        Some(DisconnectReason::ListenerShutdown)
+    );
+}
+
+#[tokio::test]
+async fn endpoints() {
+    let mut listener = listener().await;
+    let remote = listener.local_addr().unwrap();
+    let (client, session) = connect(&mut listener, 0x2).await;
+
+    assert_eq!(client.peer_guid(), 0x1);
+    assert_eq!(session.peer_guid(), 0x2);
+    assert_eq!(client.peer_addr(), remote);
+    assert_eq!(session.peer_addr(), client.local_addr());
+    assert_eq!(session.local_addr(), remote);
+    assert_eq!(client.observed_addr(), Some(client.local_addr()));
+    assert_eq!(session.observed_addr(), Some(remote));
+    assert!(client.mtu().await <= MAX_MTU_SIZE);
+}
+
+#[tokio::test]
+async fn into_split() {
+    let remote = echo_server("127.0.0.1:0").await;
+    let client = UcpSession::connect("127.0.0.1:0", remote, 0x2)
+        .await
+        .unwrap();
+    let (send, mut recv) = client.into_split();
+    for i in 0xf0..0xf4u8 {
+        let send = send.clone();
+        tokio::spawn(async move {
+            send.send(&[i; 16], Reliability::ReliableOrdered)
+                .await
+                .unwrap();
+        });
+    }
+    let mut got = vec![];
+    for _ in 0..4 {
+        got.push(within(recv.recv()).await.unwrap()[0]);
+    }
+    got.sort();
+    assert_eq!(got, vec![0xf0, 0xf1, 0xf2, 0xf3]);
+}
+
+#[tokio::test]
+async fn malformed_datagrams() {
+    let server = echo_server("127.0.0.1:0").await;
+    let raw = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    raw.send_to(&raw_ocrequest2_with_mtu(server, 10), server)
+        .await
+        .unwrap();
+    raw.send_to(&raw_ocrequest2(server), server).await.unwrap();
+    let mut v = [0u8; 2048];
+    let (size, _) = raw.recv_from(&mut v).await.unwrap();
+    assert_eq!(v[0], 0x08);
+    assert_eq!(&v[size - 3..size - 1], &1400u16.to_be_bytes());
+    // An unreliable frame claiming 8191 bytes, followed by only one.
+    raw.send_to(&[0x84, 0, 0, 0, 0x00, 0xff, 0xff, 0xfe], server)
+        .await
+        .unwrap();
+
+    let mut client = UcpSession::connect("127.0.0.1:0", server, 0x2)
+        .await
+        .unwrap();
+    assert_echo(&mut client, &[0xfe; 4]).await;
+}
+
+#[tokio::test]
+async fn config_bounds() {
+    let config = || {
+        UcpConfig::new()
+            .max_mtu_size(10)
+            .mtu_ladder(vec![40, u16::MAX])
+            .session_channel_capacity(0)
+            .listener_channel_capacity(0)
+            .tick_interval(Duration::ZERO)
+            .ping_interval(Duration::ZERO)
+            .handshake_attempts(usize::MAX)
+            .handshake_retry_interval(Duration::MAX)
+    };
+    let server = echo_server_with("127.0.0.1:0", config()).await;
+    let mut client = UcpSession::connect_with("127.0.0.1:0", server, 0x2, config())
+        .await
+        .unwrap();
+    assert_eq!(client.mtu().await, 576);
+    assert_echo(&mut client, &[0xfe; 4096]).await;
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+    let server = echo_server("127.0.0.1:0").await;
+    let limit = Arc::new(AtomicUsize::new(usize::MAX));
+    let (remote, _) = lossy_proxy(server, limit.clone()).await;
+    let config = UcpConfig::new()
+        .idle_timeout(Duration::from_millis(300))
+        .ping_interval(Duration::from_secs(60))
+        .mtu_discovery(false);
+    let mut client = UcpSession::connect_with("127.0.0.1:0", remote, 0x2, config)
+        .await
+        .unwrap();
+
+    // Quiet but alive: DetectLostConnections gets acked.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(client.disconnect_reason(), None);
+    client.send(&[0xfe], Reliability::Reliable).await.unwrap();
+    assert_eq!(client.recv().await.unwrap(), vec![0xfe]);
+
+    limit.store(0, Ordering::Relaxed);
+    assert!(matches!(
+        within(client.recv()).await,
+        Err(UcpError::Disconnected(DisconnectReason::IdleTimeout))
+    ));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::{echo_server, within};
+use ucp::{Reliability, UcpSession};
+
+#[tokio::test]
+async fn rtt() {
+    let remote = echo_server("127.0.0.1:0").await;
+    let client = UcpSession::connect("127.0.0.1:0", remote, 0x1)
+        .await
+        .unwrap();
+    let rtt = within(client.ping()).await.unwrap();
+    assert!(rtt < Duration::from_secs(1));
+
+    let smoothed = client.rtt().await.unwrap();
+    assert_eq!(client.latency().await, Some(smoothed / 2));
+    let stats = client.rtt_stats().await.unwrap();
+    assert!(stats.min <= stats.avg && stats.avg <= stats.max);
+}
+
+#[tokio::test]
+async fn stats() {
+    let remote = echo_server("127.0.0.1:0").await;
+    let mut client = UcpSession::connect("127.0.0.1:0", remote, 0x1)
+        .await
+        .unwrap();
+    client
+        .send(&[0xfe; 4096], Reliability::ReliableOrdered)
+        .await
+        .unwrap();
+    client.recv().await.unwrap();
+
+    let stats = client.stats().await;
+    assert!(stats.bytes_sent > 4096 && stats.bytes_received > 4096);
+    assert!(stats.datagrams_sent >= 4 && stats.datagrams_received >= 4);
+    assert!(stats.frames_sent >= 4);
+    assert!(stats.datagrams_acked > 0);
+    assert!(stats.ack_latency.is_some());
+    assert!(stats.cwnd > 0);
+    assert_eq!(stats.pending_fragments, 0);
+    assert_eq!(stats.out_of_order, 0);
+    assert!((0. ..=1.).contains(&stats.loss_rate()));
+}
+
+#[tokio::test]
+async fn clock_offset() {
+    let remote = echo_server("127.0.0.1:0").await;
+    let client = UcpSession::connect("127.0.0.1:0", remote, 0x1)
+        .await
+        .unwrap();
+    assert_eq!(client.remote_time_now().await, None);
+    client.ping().await.unwrap();
+
+    // Both ends share this machine's clock. The bound is loose because a slow
+    // runner can delay either timestamp; unit mistakes would be far larger.
+    let offset = client.clock_offset().await.unwrap();
+    assert!(offset.abs() < 500);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    assert!(client.remote_time_now().await.unwrap().abs_diff(now) < 500);
+    assert!(client.to_local_time(now).await.unwrap().abs_diff(now) < 500);
+}